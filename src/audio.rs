@@ -0,0 +1,100 @@
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::Sdl;
+
+use sound::SAMPLE_RATE;
+
+const HOST_RATE: i32 = 48000;
+const CHANNELS: u8 = 2;
+
+// Amount of audio we try to keep queued, in seconds. Enough to ride out a late frame.
+const TARGET_LATENCY: f64 = 0.05;
+
+// Maximum pitch deviation used by dynamic rate control, inaudible at 0.5%.
+const MAX_DELTA: f64 = 0.005;
+
+pub struct Audio {
+    queue: AudioQueue<f32>,
+
+    // Game Boy samples consumed per host sample at nominal speed.
+    ratio: f64,
+
+    target: f64,
+
+    position: f64,
+    left: f32,
+    right: f32,
+    count: u32,
+
+    buffer: Vec<f32>,
+}
+
+impl Audio {
+    pub fn new(sdl_context: &Sdl) -> Result<Audio, String> {
+        let audio_subsystem = sdl_context.audio()?;
+
+        let desired = AudioSpecDesired {
+            freq: Some(HOST_RATE),
+            channels: Some(CHANNELS),
+            samples: Some(1024),
+        };
+
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &desired)?;
+
+        let freq = queue.spec().freq as f64;
+
+        queue.resume();
+
+        Ok(Audio {
+            queue,
+
+            ratio: SAMPLE_RATE as f64 / freq,
+
+            target: freq * TARGET_LATENCY,
+
+            position: 0.0,
+            left: 0.0,
+            right: 0.0,
+            count: 0,
+
+            buffer: Vec::new(),
+        })
+    }
+
+    // Resamples interleaved stereo samples from the APU and queues them for playback.
+    pub fn push(&mut self, samples: &[f32]) {
+        // Dynamic rate control: nudge the resampling ratio so the queue hovers around the
+        // target fill. A full queue consumes input faster (fewer output samples), an empty one slower.
+        let queued = (self.queue.size() as usize / (4 * CHANNELS as usize)) as f64;
+        let fill = ((queued - self.target) / self.target).max(-1.0).min(1.0);
+        let step = self.ratio * (1.0 + MAX_DELTA * fill);
+
+        self.buffer.clear();
+
+        for frame in samples.chunks(2) {
+            // Box filter: average every input sample that falls into one output period.
+            self.left += frame[0];
+            self.right += frame[1];
+            self.count += 1;
+
+            self.position += 1.0;
+
+            if self.position >= step {
+                self.position -= step;
+
+                self.buffer.push(self.left / self.count as f32);
+                self.buffer.push(self.right / self.count as f32);
+
+                self.left = 0.0;
+                self.right = 0.0;
+                self.count = 0;
+            }
+        }
+
+        // If emulation stalled for a long time drop the backlog instead of lagging behind video.
+        if queued > self.target * 4.0 {
+            self.queue.clear();
+        }
+
+        self.queue.queue(&self.buffer);
+    }
+}
@@ -1,8 +1,11 @@
 use clock::Clock;
 use sound::Sound;
 use gui::*;
-use joypad::Joypad;
+use joypad::{Button, Joypad};
 use serial::Serial;
+use mbc::CartridgeHeader;
+
+use std::path::PathBuf;
 
 mod map {
     pub struct Range(u16, u16);
@@ -50,15 +53,21 @@ mod map {
     pub const HIGH_INTERNAL_RAM: Range = Range(0xFF80, 0xFFFE);
 }
 
+// Dirty battery RAM is flushed to disk about every 5 seconds of emulated time.
+const SAVE_INTERVAL: u32 = 5 * 4194304;
+
 pub struct Bus {
+    header: CartridgeHeader,
     mbc: Box<::mbc::MBC+'static>,
+    save_path: Option<PathBuf>,
+    save_cycles: u32,
+
+    // T-cycles elapsed since power on.
+    cycles: u64,
 
     clock: Clock,
 
-    sound_channel_1: Sound,
-    sound_channel_2: Sound,
-    sound_channel_3: Sound,
-    sound_channel_4: Sound,
+    pub sound: Sound,
 
     pub gui: Gui,
 
@@ -74,15 +83,17 @@ pub struct Bus {
 }
 
 impl Bus {
-    pub fn new(mbc: Box<::mbc::MBC+'static>) -> Bus {
+    pub fn new(header: CartridgeHeader, mbc: Box<::mbc::MBC+'static>) -> Bus {
         Bus {
+            header,
             mbc,
+            save_path: None,
+            save_cycles: 0,
+
+            cycles: 0,
             clock: Clock::new(),
 
-            sound_channel_1: Sound::new(),
-            sound_channel_2: Sound::new(),
-            sound_channel_3: Sound::new(),
-            sound_channel_4: Sound::new(),
+            sound: Sound::new(),
 
             gui: Gui::new(),
 
@@ -99,11 +110,105 @@ impl Bus {
     }
 
     pub fn add_to_clock(&mut self, value: u16) {
-        self.clock.counter = self.clock.counter.wrapping_add(value);
+        self.cycles += value as u64;
+
+        self.clock.step(value);
+
+        for _ in 0..self.clock.sequencer_ticks {
+            self.sound.step_frame_sequencer();
+        }
+        self.clock.sequencer_ticks = 0;
+
+        self.sound.step(value);
+
+        if self.clock.interrupt {
+            self.clock.interrupt = false;
+            self.request_interrupt(Interrupt::Timer);
+        }
+
+        self.gui.step(value);
+
+        if self.gui.v_blank_interrupt {
+            self.gui.v_blank_interrupt = false;
+            self.request_interrupt(Interrupt::VBlank);
+        }
+
+        if self.gui.lcd_stat_interrupt {
+            self.gui.lcd_stat_interrupt = false;
+            self.request_interrupt(Interrupt::LcdStat);
+        }
+
+        self.save_cycles += value as u32;
+        if self.save_cycles >= SAVE_INTERVAL {
+            self.save_cycles = 0;
+            if self.mbc.take_ram_dirty() {
+                self.save();
+            }
+        }
+    }
+
+    pub fn serial_output(&self) -> &[u8] {
+        &self.serial.output
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.ifl.set(interrupt, true);
+    }
+
+    pub fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.ifl.set(interrupt, false);
+    }
+
+    // Highest priority interrupt that is both requested and enabled.
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.ie.get_data() & self.ifl.get_data();
+
+        Interrupt::all()
+            .iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+            .cloned()
+    }
+
+    pub fn set_save_path(&mut self, path: PathBuf) {
+        self.save_path = Some(path);
+    }
+
+    pub fn save(&self) {
+        if !self.mbc.has_battery() {
+            return;
+        }
+
+        if let Some(ref path) = self.save_path {
+            if let Err(e) = ::mbc::write_save(&*self.mbc, path) {
+                println!("Failed to write {}: {}", path.display(), e);
+            }
+        }
     }
 
-    pub fn get_banks_count(&self, value: u8) -> u32 {
-        ::mbc::bank_count(value)
+    pub fn rumble_event(&mut self) -> Option<bool> {
+        self.mbc.rumble_event()
+    }
+
+    pub fn enable_access_blocking(&mut self) {
+        self.gui.access_blocking = true;
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button(button, pressed) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn is_joypad_pressed(&self) -> bool {
+        self.joypad.get_keys() & 0x0F != 0x0F
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
     }
 
     pub fn load(&self, addr: u16) -> u8 {
@@ -111,26 +216,33 @@ impl Bus {
             return self.mbc.readrom(offset)
         }
 
-        if let Some(offset) = map::SWITCHABLE_ROM.contains(addr) {
-            return self.mbc.readrom(offset)
+        // MBCs decode the full CPU address, bank registers live at 0x2000-0x7FFF.
+        if let Some(_) = map::SWITCHABLE_ROM.contains(addr) {
+            return self.mbc.readrom(addr)
         }
 
-        if let Some(offset) = map::VIDEO_RAM.contains(addr) {
-            
+        if let Some(_) = map::VIDEO_RAM.contains(addr) {
+            if !self.gui.is_vram_accessible() {
+                return 0xFF;
+            }
+
             if let Some(offset) = map::CHARACTER_DATA.contains(addr) {
-                panic!("load CHARACTER_DATA");
+                return self.gui.load_character_data(offset);
             }
 
             if let Some(offset) = map::BG_DISPLAY_DATA_1.contains(addr) {
-                return self.gui.load_bg_display_data_1(addr);
+                return self.gui.load_bg_display_data_1(offset);
             }
 
             if let Some(offset) = map::BG_DISPLAY_DATA_2.contains(addr) {
-                return self.gui.load_bg_display_data_2(addr);       
+                return self.gui.load_bg_display_data_2(offset);
             }
         }
 
         if let Some(offset) = map::SPRITE_ATTRIB_MEMORY.contains(addr) {
+            if !self.gui.is_oam_accessible() {
+                return 0xFF;
+            }
             return self.gui.load_sprite(offset);
         }
 
@@ -143,7 +255,7 @@ impl Bus {
         }
 
         if let Some(offset) = map::INTERNAL_RAM_BANK1.contains(addr) {
-            return self.wram[offset as usize & 0x0FFF];
+            return self.wram[0x1000 | offset as usize & 0x0FFF];
         }
 
         if let Some(offset) = map::ECHO_INTERNAL_RAM_BANK0.contains(addr) {
@@ -151,7 +263,7 @@ impl Bus {
         }
 
         if let Some(offset) = map::ECHO_INTERNAL_RAM_BANK1.contains(addr) {
-            return self.wram[offset as usize & 0x0FFF];
+            return self.wram[0x1000 | offset as usize & 0x0FFF];
         }
 
         if let Some(offset) = map::HIGH_INTERNAL_RAM.contains(addr) {
@@ -162,8 +274,9 @@ impl Bus {
             return 0;
         }
 
+        // Unused IO addresses, like the unimplemented registers below, read as open bus.
         if let Some(offset) = map::NOT_USABLE_2.contains(addr) {
-            return 0;
+            return 0xFF;
         }
 
         if let Some(offset) = map::IO.contains(addr) {
@@ -178,117 +291,29 @@ impl Bus {
                     return self.serial.control;
                 }
                 0xFF03 => {
-                    return 0;
+                    return 0xFF;
                 }
                 0xFF04 => {
-                    return self.clock.divider;
+                    return self.clock.get_divider();
                 }
                 0xFF05 => {
-                    return self.clock.counter as u8;
+                    return self.clock.counter;
                 }
                 0xFF06 => {
-                    return self.clock.divider;
+                    return self.clock.modulo;
                 }
                 0xFF07 => {
-                    let status = self.clock.enabled as u8;
-                    let value = match self.clock.mode {
-                        4096 => 0x00,
-                        262144 => 0x01,
-                        65536 => 0x10,
-                        16384 => 0x11,
-                        _ => unreachable!(),
-                    };
-                    return (status << 2) | value;
+                    return self.clock.get_control();
                 }
                 0xFF08 | 0xFF09 | 0xFF0A | 0xFF0B | 0xFF0C | 0xFF0D | 0xFF0E => {
-                    return 0;
+                    return 0xFF;
                 }
                 0xFF0F => {
-                    return self.ifl.get_data();
-                }
-                0xFF10 => {
-                    let time = match self.sound_channel_1.sweep_time.round() as i32 {
-                        0 => 0b000,
-                        8 => 0b001,
-                        16 => 0b010,
-                        23 => 0b011,
-                        31 => 0b100,
-                        39 => 0b101,
-                        47 => 0b110,
-                        55 => 0b111,
-                        _ => unreachable!(),
-                    };
-
-                    return time << 4 | (self.sound_channel_1.sweep_mode as u8) << 3
-                        | self.sound_channel_1.shift as u8;
-                }
-                0xFF11 => {
-                    let pattern = match self.sound_channel_1.wave_pattern.round() as i32 {
-                        13 => 0b00,
-                        25 => 0b01,
-                        50 => 0b10,
-                        75 => 0b11,
-                        _ => unreachable!(),
-                    };
-
-                    return pattern << 6 | self.sound_channel_1.length as u8;
+                    // Upper three bits are unused and always read as 1.
+                    return 0xE0 | self.ifl.get_data();
                 }
-                0xFF12 => {
-                    return (self.sound_channel_1.initial_volume << 4
-                        | (self.sound_channel_1.direction as u16) << 3
-                        | self.sound_channel_1.sweeps as u16) as u8;
-                }
-                0xFF13 | 0xFF15 | 0xFF18 => {
-                    return 0;
-                }
-                0xFF14 => {
-                    return self.sound_channel_1.initial << 7 | self.sound_channel_1.counter << 6
-                        | self.sound_channel_1.frequency;
-                }
-                0xFF16 => {
-                    let pattern = match self.sound_channel_2.wave_pattern.round() as i32 {
-                        13 => 0b00,
-                        25 => 0b01,
-                        50 => 0b10,
-                        75 => 0b11,
-                        _ => unreachable!(),
-                    };
-
-                    return (pattern << 6 | self.sound_channel_2.length) as u8;
-                }
-                0xFF17 => {
-                    return (self.sound_channel_2.initial_volume << 4
-                        | (self.sound_channel_2.direction as u16) << 3
-                        | self.sound_channel_2.sweeps as u16) as u8;
-                }
-                0xFF19 => {
-                    return self.sound_channel_2.initial << 7 | self.sound_channel_2.counter << 6
-                        | self.sound_channel_2.frequency;
-                }
-                0xFF1A => {
-                    return self.sound_channel_3.enable as u8;
-                }
-                0xFF1B => {
-                    return self.sound_channel_3.length as u8;
-                }
-                0xFF1C => {
-                    let volume = match self.sound_channel_3.volume {
-                        0 => 0b00,
-                        100 => 0b01,
-                        50 => 0b10,
-                        25 => 0b11,
-                        _ => unreachable!(),
-                    };
-
-                    return volume;
-                }
-                0xFF1D | 0xFF1F => return 0,
-                0xFF1E => {
-                    return self.sound_channel_3.initial << 7 | self.sound_channel_3.counter << 6
-                        | self.sound_channel_3.frequency;
-                }
-                0xFF20 => {
-                    return self.sound_channel_4.length as u8;
+                0xFF10 ... 0xFF3F => {
+                    return self.sound.load(addr);
                 }
                 0xFF40 => {
                     let window_tile = match self.gui.window_tile_map {
@@ -303,7 +328,7 @@ impl Bus {
                         _ => unreachable!(),
                     };
 
-                    let bg_tile = match self.gui.window_tile_map {
+                    let bg_tile = match self.gui.bg_tile_map {
                         0x9800 => 0b0,
                         0x9C00 => 0b1,
                         _ => unreachable!(),
@@ -323,7 +348,7 @@ impl Bus {
                         | self.gui.bg_display as u8;
                 }
                 0xFF41 => {
-                    return 0x80 | self.gui.lyc << 6 | self.gui.mode2 << 5 | self.gui.mode1 << 4 | self.gui.mode0 << 3 | self.gui.coincidence << 2 | self.gui.mode_flag;
+                    return 0x80 | self.gui.lyc_interrupt << 6 | self.gui.mode2 << 5 | self.gui.mode1 << 4 | self.gui.mode0 << 3 | self.gui.coincidence << 2 | self.gui.mode_flag;
                 }
                 0xFF42 => {
                     return self.gui.scroll_y;
@@ -354,25 +379,21 @@ impl Bus {
                 0xFF4B => {
                     return self.gui.window_x;
                 }
-                _ => panic!("Unimplemented load IO register {:#x}", addr),
+                // Unimplemented and unused registers read as open bus.
+                _ => return 0xFF,
             }
         }
 
         if addr == 0xFFFF {
-            let value: u8 = ((self.ie.joypad as u8) << 4) | ((self.ie.serial as u8) << 3)
-                | ((self.ie.timer as u8) << 2)
-                | ((self.ie.lcd_stat as u8) << 1)
-                | self.ie.v_blank as u8;
-            return value;
+            return self.ie.get_data();
         }
 
-        panic!("Unhandled load 8bit address {:#x}", addr);
+        0xFF
     }
 
     pub fn store16(&mut self, addr: u16, value: u16) {
-        // TODO: Probably incorrect. Inverse needed.
-        self.store(addr, (value >> 8) as u8);
-        self.store(addr + 1, (value & 0xFF) as u8);
+        self.store(addr, (value & 0xFF) as u8);
+        self.store(addr.wrapping_add(1), (value >> 8) as u8);
     }
 
     pub fn store(&mut self, addr: u16, value: u8) {
@@ -388,18 +409,21 @@ impl Bus {
             return;
         }
 
-        if let Some(offset) = map::VIDEO_RAM.contains(addr) {
-            
+        if let Some(_) = map::VIDEO_RAM.contains(addr) {
+            if !self.gui.is_vram_accessible() {
+                return;
+            }
+
             if let Some(offset) = map::CHARACTER_DATA.contains(addr) {
-                panic!("store CHARACTER_DATA");
+                return self.gui.store_character_data(offset, value);
             }
 
             if let Some(offset) = map::BG_DISPLAY_DATA_1.contains(addr) {
-                return self.gui.store_bg_display_data_1(addr, value);         
+                return self.gui.store_bg_display_data_1(offset, value);
             }
 
             if let Some(offset) = map::BG_DISPLAY_DATA_2.contains(addr) {
-                return self.gui.store_bg_display_data_2(addr, value);          
+                return self.gui.store_bg_display_data_2(offset, value);
             }
         }
 
@@ -407,18 +431,27 @@ impl Bus {
             return self.hram[offset as usize & 0x007F] = value;
         }
 
-        if let Some(offset) = map::SWITCHABLE_ROM.contains(addr) {
-            return self.mbc.writerom(offset, value);
+        if let Some(_) = map::SWITCHABLE_ROM.contains(addr) {
+            return self.mbc.writerom(addr, value);
+        }
+
+        if let Some(offset) = map::SWITCHABLE_RAM.contains(addr) {
+            return self.mbc.writeram(offset, value);
         }
 
         if let Some(offset) = map::SPRITE_ATTRIB_MEMORY.contains(addr) {
+            if !self.gui.is_oam_accessible() {
+                return;
+            }
             return self.gui.store_sprite(offset, value);
         }
 
         if let Some(offset) = map::IO.contains(addr) {
             match addr {
                 0xFF00 => {
-                    //TODO: Implement Joypad interrupt.
+                    if self.joypad.set_select(value) {
+                        self.request_interrupt(Interrupt::Joypad);
+                    }
                     return;
                 }
                 0xFF01 => {
@@ -427,262 +460,45 @@ impl Bus {
                 0xFF02 => {
                     self.serial.transfer_flag = (value >> 7) & 0b1 == 1;
                     self.serial.clock = value & 0b1 == 1;
-                    return self.serial.control = value;
+                    self.serial.control = value;
+
+                    // No link partner: an internally clocked transfer completes at once and shifts in 0xFF.
+                    if self.serial.transfer_flag && self.serial.clock {
+                        let data = self.serial.data;
+                        self.serial.output.push(data);
+                        self.serial.data = 0xFF;
+                        self.serial.transfer_flag = false;
+                        self.serial.control &= 0x7F;
+                        self.request_interrupt(Interrupt::Serial);
+                    }
+                    return;
                 }
                 0xFF03 => {
                     return;
                 }
                 0xFF04 => {
-                    return self.clock.divider = 0;
+                    return self.clock.reset_divider();
                 }
                 0xFF05 => {
-                    let step = (self.clock.mode / 1024) as u16;
-
-                    if step + self.clock.counter > 0xFF {
-                        self.clock.counter = self.clock.divider as u16;
-                    // TODO: Probably needed implement Interrupt FF0F and set bit 2 inside as ENABLED
-                    // More description: When that interrupt is enabled, then the CPU will execute it by calling the timer interrupt vector at 0050h.
-                    } else {
-                        self.clock.counter = self.clock.counter.wrapping_add(step);
-                    }
-                    return;
+                    return self.clock.set_counter(value);
                 }
                 0xFF06 => {
-                    return self.clock.divider = value;
+                    return self.clock.modulo = value;
                 }
                 0xFF07 => {
-                    let status = ((value & 0b100) >> 2) == 1;
-                    self.clock.enabled = status;
-                    self.clock.mode = match value & 0b11 {
-                        0b00 => 4096,
-                        0b01 => 262144,
-                        0b10 => 65536,
-                        0b11 => 16384,
-                        _ => unreachable!(),
-                    };
-                    return;
+                    return self.clock.set_control(value);
                 }
                 0xFF08 ... 0xFF0E => {
                     return;
                 }
                 0xFF0F => {
-                    self.ifl.v_blank = value & 0b1 == 1;
-                    self.ifl.lcd_stat = (value >> 1) & 0b1 == 1;
-                    self.ifl.timer = (value >> 2) & 0b1 == 1;
-                    self.ifl.serial = (value >> 3) & 0b1 == 1;
-                    self.ifl.joypad = (value >> 4) & 0b1 == 1;
-                    return;
+                    return self.ifl.set_data(value);
                 }
-                0xFF10 => {
-                    let time = (value >> 4) & 0b111;
-                    self.sound_channel_1.sweep_time = match time {
-                        0b000 => 0.0 as f32,
-                        0b001 => 7.8 as f32,
-                        0b010 => 15.6 as f32,
-                        0b011 => 23.4 as f32,
-                        0b100 => 31.3 as f32,
-                        0b101 => 39.1 as f32,
-                        0b110 => 46.9 as f32,
-                        0b111 => 54.7 as f32,
-                        _ => unreachable!(),
-                    };
-                    self.sound_channel_1.sweep_mode = ((value & 0xf) as u8) == 1;
-                    self.sound_channel_1.shift = (value & 0b111) as u32;
-
-                    return;
-                }
-                0xFF11 => {
-                    // TODO: Probably needed implement manual wave duty pattern.
-                    let pattern = (value >> 6) & 0b11;
-                    self.sound_channel_1.wave_pattern = match pattern {
-                        0b00 => 12.5 as f32,
-                        0b01 => 25.0 as f32,
-                        0b10 => 50.0 as f32,
-                        0b11 => 75.0 as f32,
-                        _ => unreachable!(),
-                    };
-
-                    let t1 = (value & 0x3f) as u32;
-                    self.sound_channel_1.length = (64 - t1) * (1 / 256);
-
-                    return;
-                }
-                0xFF12 => {
-                    self.sound_channel_1.initial_volume = (value >> 4) as u16;
-                    self.sound_channel_1.direction = (value >> 3) & 0b1 == 1;
-                    self.sound_channel_1.sweeps = value & 0x4;
-
-                    return;
-                }
-                0xFF13 => {
-                    // TODO:
-                    // FF13 - NR13 - Channel 1 Frequency lo (Write Only)
-                    // Lower 8 bits of 11 bit frequency (x).
-                    // Next 3 bit are in NR14 ($FF14)
-                    return;
-                }
-                0xFF14 => {
-                    self.sound_channel_1.initial = (value >> 7) & 0b1;
-                    self.sound_channel_1.counter = (value >> 6) & 0b1;
-                    self.sound_channel_1.frequency = value & 0b111;
-
-                    return;
-                }
-                0xFF15 => {
-                    return;
-                }
-                0xFF16 => {
-                    // TODO: Probably needed implement manual wave duty pattern.
-                    let pattern = (value >> 6) & 0b11;
-                    self.sound_channel_2.wave_pattern = match pattern {
-                        0b00 => 12.5 as f32,
-                        0b01 => 25.0 as f32,
-                        0b10 => 50.0 as f32,
-                        0b11 => 75.0 as f32,
-                        _ => unreachable!(),
-                    };
-
-                    let t1 = (value & 0x3f) as u32;
-                    self.sound_channel_2.length = (64 - t1) * (1 / 256);
-
-                    return;
-                }
-                0xFF17 => {
-                    self.sound_channel_2.initial_volume = (value >> 4) as u16;
-                    self.sound_channel_2.direction = (value >> 3) & 0b1 == 1;
-                    self.sound_channel_2.sweeps = value & 0x4;
-
-                    return;
-                }
-                0xFF18 => {
-                    // TODO:
-                    // FF18 - NR23 - Channel 2 Frequency lo data (W)
-                    // Frequency's lower 8 bits of 11 bit data (x).
-                    // Next 3 bits are in NR24 ($FF19).
-                    return;
-                }
-                0xFF19 => {
-                    self.sound_channel_2.initial = (value >> 7) & 0b1;
-                    self.sound_channel_2.counter = (value >> 6) & 0b1;
-                    self.sound_channel_2.frequency = value & 0b111;
-
-                    return;
-                }
-                0xFF1A => {
-                    self.sound_channel_3.enable = (value >> 7) & 0b1 == 1;
-
-                    return;
-                }
-                0xFF1B => {
-                    self.sound_channel_3.length = ((256 as u32 - value as u32) * (1 / 256)) as u32;
-
-                    return;
-                }
-                0xFF1C => {
-                    let volume = (value >> 5) & 0b11;
-                    self.sound_channel_3.volume = match volume {
-                        0b00 => 0,
-                        0b01 => 100,
-                        0b10 => 50,
-                        0b11 => 25,
-                        _ => unreachable!(),
-                    };
-
-                    return;
-                }
-                0xFF1D => {
-                    // TODO:
-                    // FF1D - NR33 - Channel 3 Frequency's lower data (W)
-                    // Lower 8 bits of an 11 bit frequency (x).
-                    return;
-                }
-                0xFF1E => {
-                    self.sound_channel_3.initial = (value >> 7) & 0b1;
-                    self.sound_channel_3.counter = (value >> 6) & 0b1;
-                    self.sound_channel_3.frequency = value & 0b111;
-
-                    return;
-                }
-                0xFF1F => {
-                    return;
-                }
-                0xFF20 => {
-                    let t1 = (value & 0x3f) as u32;
-                    self.sound_channel_4.length = (64 - t1) * (1 / 256);
-
-                    return;
-                }
-                0xFF21 => {
-                    self.sound_channel_4.initial_volume = (value >> 4) as u16;
-                    self.sound_channel_4.direction = (value >> 3) & 0b1 == 1;
-                    self.sound_channel_4.sweeps = value & 0x4;
-
-                    return;
-                }
-                0xFF22 => {
-                    self.sound_channel_4.shift_clock = (value >> 4) as u32;
-                    self.sound_channel_4.width = ((value >> 3) & 0b1) as u32;
-                    self.sound_channel_4.ratio = (value & 0b111) as u32;
-
-                    return;
-                }
-                0xFF23 => {
-                    self.sound_channel_4.initial = (value >> 7) & 0b1;
-                    self.sound_channel_4.counter = (value >> 6) & 0b1;
-
-                    return;
+                0xFF10 ... 0xFF3F => {
+                    return self.sound.store(addr, value);
                 }
-                0xFF24 => {
-                    self.sound_channel_2.enable = (value >> 7) & 0b1 == 1;
-                    self.sound_channel_2.volume = ((value >> 4) & 0b111) as u32;
-
-                    self.sound_channel_1.enable = (value >> 3) & 0b1 == 1;
-                    self.sound_channel_1.volume = (value & 0b111) as u32;
-
-                    return;
-                }
-                0xFF25 => {
-                    // TODO: Probably incorrect implementation.
-                    self.sound_channel_2.data[7] = (value >> 7) & 0b1;
-                    self.sound_channel_2.data[6] = (value >> 6) & 0b1;
-                    self.sound_channel_2.data[5] = (value >> 5) & 0b1;
-                    self.sound_channel_2.data[4] = (value >> 4) & 0b1;
-
-                    self.sound_channel_1.data[3] = (value >> 3) & 0b1;
-                    self.sound_channel_1.data[2] = (value >> 2) & 0b1;
-                    self.sound_channel_1.data[1] = (value >> 1) & 0b1;
-                    self.sound_channel_1.data[0] = value & 0b1;
-
-                    return;
-                }
-                0xFF26 => {
-                    // TODO: Probably incorrect implementation.
-                    let all = (value >> 7) & 0b1 == 1;
-
-                    if all {
-                        self.sound_channel_1.enable = true;
-                        self.sound_channel_2.enable = true;
-                        self.sound_channel_3.enable = true;
-                        self.sound_channel_4.enable = true;
-                    } else {
-                        self.sound_channel_1.enable = false;
-                        self.sound_channel_2.enable = false;
-                        self.sound_channel_3.enable = false;
-                        self.sound_channel_4.enable = false;
-                    }
-
-                    return;
-                }
-                0xFF27 ... 0xFF2F => {
-                    return;
-                }
-                0xFF30 ... 0xFF3F => {
-                    self.sound_channel_3.waveram[(addr as usize - 0xFF30) / 2] = value >> 4;
-                    self.sound_channel_3.waveram[((addr as usize - 0xFF30) / 2) + 1] = value & 0xF;
-                    return;
-                },
                 0xFF40 => {
-                    self.gui.lcd_display = (value >> 7) & 0b1 == 1;
+                    self.gui.set_lcd_display((value >> 7) & 0b1 == 1);
 
                     let window_tile = (value >> 6) & 0b1;
 
@@ -704,7 +520,7 @@ impl Bus {
 
                     let bg_tile = (value >> 3) & 0b1;
 
-                    self.gui.window_tile_map = match bg_tile {
+                    self.gui.bg_tile_map = match bg_tile {
                         0b0 => 0x9800,
                         0b1 => 0x9C00,
                         _ => unreachable!(),
@@ -725,7 +541,7 @@ impl Bus {
                     return;
                 }
                 0xFF41 => {
-                    self.gui.lyc = (value >> 6) & 0b1;
+                    self.gui.lyc_interrupt = (value >> 6) & 0b1;
 
                     self.gui.mode2 = (value >> 5) & 0b1;
                     self.gui.mode1 = (value >> 4) & 0b1;
@@ -741,8 +557,7 @@ impl Bus {
                     return;
                 }
                 0xFF44 => {
-                    // Probably must be unreachable.
-                    self.gui.line = value;
+                    // LY is read-only.
                     return;
                 }
                 0xFF45 => {
@@ -751,9 +566,10 @@ impl Bus {
                 }
                 0xFF46 => {
                     let base = (value as u16) << 8;
+                    // DMA writes OAM directly, it is not subject to PPU mode blocking.
                     for i in 0 .. 0xA0 {
                         let b = self.load(base + i);
-                        self.store(0xFE00 + i, b);
+                        self.gui.store_sprite(i, b);
                     }
                     return;
                 }
@@ -813,18 +629,55 @@ impl Bus {
         }
 
         if addr == 0xFFFF {
-            self.ie.v_blank = value & 0b1 == 1;
-            self.ie.lcd_stat = (value >> 1) & 0b1 == 1;
-            self.ie.timer = (value >> 2) & 0b1 == 1;
-            self.ie.serial = (value >> 3) & 0b1 == 1;
-            self.ie.joypad = (value >> 4) & 0b1 == 1;
-            return;
+            return self.ie.set_data(value);
         }
 
         panic!("Unhandled store 8bit address {:#x}", addr);
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    // Ordered by priority, highest first.
+    pub fn all() -> [Interrupt; 5] {
+        [
+            Interrupt::VBlank,
+            Interrupt::LcdStat,
+            Interrupt::Timer,
+            Interrupt::Serial,
+            Interrupt::Joypad,
+        ]
+    }
+
+    pub fn mask(&self) -> u8 {
+        match *self {
+            Interrupt::VBlank => 0b00001,
+            Interrupt::LcdStat => 0b00010,
+            Interrupt::Timer => 0b00100,
+            Interrupt::Serial => 0b01000,
+            Interrupt::Joypad => 0b10000,
+        }
+    }
+
+    pub fn vector(&self) -> u16 {
+        match *self {
+            Interrupt::VBlank => 0x0040,
+            Interrupt::LcdStat => 0x0048,
+            Interrupt::Timer => 0x0050,
+            Interrupt::Serial => 0x0058,
+            Interrupt::Joypad => 0x0060,
+        }
+    }
+}
+
 struct InterruptEnable {
     v_blank: bool,
     lcd_stat: bool,
@@ -848,6 +701,14 @@ impl InterruptEnable {
         return (self.joypad as u8) << 4 | (self.serial as u8) << 3 | (self.timer as u8) << 2
             | (self.lcd_stat as u8) << 1 | (self.v_blank as u8);
     }
+
+    pub fn set_data(&mut self, value: u8) {
+        self.v_blank = value & 0b1 == 1;
+        self.lcd_stat = (value >> 1) & 0b1 == 1;
+        self.timer = (value >> 2) & 0b1 == 1;
+        self.serial = (value >> 3) & 0b1 == 1;
+        self.joypad = (value >> 4) & 0b1 == 1;
+    }
 }
 
 struct InterruptFlag {
@@ -873,4 +734,22 @@ impl InterruptFlag {
         return (self.joypad as u8) << 4 | (self.serial as u8) << 3 | (self.timer as u8) << 2
             | (self.lcd_stat as u8) << 1 | (self.v_blank as u8);
     }
+
+    pub fn set_data(&mut self, value: u8) {
+        self.v_blank = value & 0b1 == 1;
+        self.lcd_stat = (value >> 1) & 0b1 == 1;
+        self.timer = (value >> 2) & 0b1 == 1;
+        self.serial = (value >> 3) & 0b1 == 1;
+        self.joypad = (value >> 4) & 0b1 == 1;
+    }
+
+    pub fn set(&mut self, interrupt: Interrupt, value: bool) {
+        match interrupt {
+            Interrupt::VBlank => self.v_blank = value,
+            Interrupt::LcdStat => self.lcd_stat = value,
+            Interrupt::Timer => self.timer = value,
+            Interrupt::Serial => self.serial = value,
+            Interrupt::Joypad => self.joypad = value,
+        }
+    }
 }
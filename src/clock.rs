@@ -1,9 +1,18 @@
 pub struct Clock {
-    pub divider: u8,
-    pub counter: u16,
-    pub mode: u32,
+    pub divider: u16, // Internal 16-bit system counter, DIV (FF04) is the upper byte.
+    pub counter: u8,  // TIMA
+    pub modulo: u8,   // TMA
+    pub mode: u32,    // Input clock in Hz selected by TAC bits 0-1.
 
     pub enabled: bool,
+
+    pub interrupt: bool,
+
+    // Falling edges of DIV bit 4 since the bus last drained them, they clock the APU frame sequencer.
+    pub sequencer_ticks: u8,
+
+    // TIMA reads 0x00 for one M-cycle after overflow before TMA is loaded.
+    reload_pending: bool,
 }
 
 impl Clock {
@@ -11,9 +20,216 @@ impl Clock {
         Clock {
             divider: 0,
             counter: 0,
-            mode: 0,
+            modulo: 0,
+            mode: 4096,
 
             enabled: false,
+
+            interrupt: false,
+
+            sequencer_ticks: 0,
+
+            reload_pending: false,
+        }
+    }
+
+    pub fn get_divider(&self) -> u8 {
+        (self.divider >> 8) as u8
+    }
+
+    // Advances the system counter, TIMA ticks on a falling edge of the bit selected by TAC.
+    pub fn step(&mut self, cycles: u16) {
+        for _ in 0..cycles / 4 {
+            if self.reload_pending {
+                self.reload_pending = false;
+                self.counter = self.modulo;
+                self.interrupt = true;
+            }
+
+            let old_signal = self.signal();
+            let old_divider = self.divider;
+
+            self.divider = self.divider.wrapping_add(4);
+
+            if old_signal && !self.signal() {
+                self.increment();
+            }
+
+            if Clock::sequencer_bit(old_divider) && !Clock::sequencer_bit(self.divider) {
+                self.sequencer_ticks += 1;
+            }
+        }
+    }
+
+    pub fn reset_divider(&mut self) {
+        // Resetting the counter can itself produce a falling edge.
+        let old_signal = self.signal();
+
+        if Clock::sequencer_bit(self.divider) {
+            self.sequencer_ticks += 1;
+        }
+
+        self.divider = 0;
+
+        if old_signal {
+            self.increment();
         }
     }
+
+    pub fn set_counter(&mut self, value: u8) {
+        // Writing TIMA during the overflow cycle cancels the reload and the interrupt.
+        self.reload_pending = false;
+        self.counter = value;
+    }
+
+    pub fn set_control(&mut self, value: u8) {
+        let old_signal = self.signal();
+
+        self.enabled = (value >> 2) & 0b1 == 1;
+        self.mode = match value & 0b11 {
+            0b00 => 4096,
+            0b01 => 262144,
+            0b10 => 65536,
+            0b11 => 16384,
+            _ => unreachable!(),
+        };
+
+        // Disabling the timer or switching to a lower bit while the old one is high ticks TIMA.
+        if old_signal && !self.signal() {
+            self.increment();
+        }
+    }
+
+    pub fn get_control(&self) -> u8 {
+        let value = match self.mode {
+            4096 => 0b00,
+            262144 => 0b01,
+            65536 => 0b10,
+            16384 => 0b11,
+            _ => unreachable!(),
+        };
+
+        0xF8 | (self.enabled as u8) << 2 | value
+    }
+
+    fn bit(&self) -> u16 {
+        match self.mode {
+            4096 => 9,
+            262144 => 3,
+            65536 => 5,
+            16384 => 7,
+            _ => unreachable!(),
+        }
+    }
+
+    fn sequencer_bit(divider: u16) -> bool {
+        (divider >> 12) & 0b1 == 1
+    }
+
+    fn signal(&self) -> bool {
+        self.enabled && (self.divider >> self.bit()) & 0b1 == 1
+    }
+
+    fn increment(&mut self) {
+        let (value, overflow) = self.counter.overflowing_add(1);
+
+        self.counter = value;
+
+        if overflow {
+            self.reload_pending = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Timer running at 262144 Hz, TIMA ticks when bit 3 of the system counter falls.
+    fn fast_timer() -> Clock {
+        let mut clock = Clock::new();
+        clock.set_control(0b101);
+        clock
+    }
+
+    #[test]
+    fn counts_on_falling_edges() {
+        let mut clock = fast_timer();
+
+        clock.step(12);
+        assert_eq!(clock.counter, 0);
+        clock.step(4);
+        assert_eq!(clock.counter, 1);
+
+        clock.step(16 * 9);
+        assert_eq!(clock.counter, 10);
+        assert_eq!(clock.get_divider(), 0);
+        clock.step(96);
+        assert_eq!(clock.get_divider(), 1);
+    }
+
+    #[test]
+    fn div_reset_ticks_when_selected_bit_is_high() {
+        let mut clock = fast_timer();
+        clock.step(8);
+        clock.reset_divider();
+        assert_eq!(clock.counter, 1);
+        assert_eq!(clock.divider, 0);
+
+        let mut clock = fast_timer();
+        clock.step(4);
+        clock.reset_divider();
+        assert_eq!(clock.counter, 0);
+    }
+
+    #[test]
+    fn tac_write_glitches() {
+        // Disabling the timer while the selected bit is high ticks TIMA.
+        let mut clock = fast_timer();
+        clock.step(8);
+        clock.set_control(0b001);
+        assert_eq!(clock.counter, 1);
+
+        // So does switching to a bit that is low.
+        let mut clock = fast_timer();
+        clock.step(8);
+        clock.set_control(0b100);
+        assert_eq!(clock.counter, 1);
+
+        // Switching while the old bit is low doesn't.
+        let mut clock = fast_timer();
+        clock.step(4);
+        clock.set_control(0b100);
+        assert_eq!(clock.counter, 0);
+        assert_eq!(clock.get_control(), 0xFC);
+    }
+
+    #[test]
+    fn overflow_reloads_tma_one_cycle_later() {
+        let mut clock = fast_timer();
+        clock.modulo = 0xAB;
+        clock.counter = 0xFF;
+
+        clock.step(16);
+        assert_eq!(clock.counter, 0x00);
+        assert!(!clock.interrupt);
+
+        clock.step(4);
+        assert_eq!(clock.counter, 0xAB);
+        assert!(clock.interrupt);
+    }
+
+    #[test]
+    fn tima_write_cancels_pending_reload() {
+        let mut clock = fast_timer();
+        clock.modulo = 0xAB;
+        clock.counter = 0xFF;
+
+        clock.step(16);
+        clock.set_counter(0x12);
+
+        clock.step(4);
+        assert_eq!(clock.counter, 0x12);
+        assert!(!clock.interrupt);
+    }
 }
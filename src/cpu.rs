@@ -1,5 +1,11 @@
 use bus::Bus;
 use register::Register;
+use joypad::Button;
+
+pub const CLOCK_SPEED: u64 = 4194304;
+
+// 154 lines of 456 dots, a frame lasts 70224 / 4194304 s (59.73 Hz).
+pub const FRAME_CYCLES: u64 = 70224;
 
 pub struct Cpu {
     bus: Bus,
@@ -13,7 +19,6 @@ pub struct Cpu {
 
     ime: bool,
 
-    di: u32,
     ei: u32,
 
     log: bool,
@@ -21,6 +26,14 @@ pub struct Cpu {
     cartrige_type: u8,
 
     halted: bool,
+    halt_bug: bool,
+
+    stopped: bool,
+
+    locked: bool,
+
+    // Bus cycle count at which the current frame ends.
+    frame_end: u64,
 }
 
 impl Cpu {
@@ -35,9 +48,8 @@ impl Cpu {
 
             current_pc: 0x0,
 
-            ime: true,
+            ime: false,
 
-            di: 0,
             ei: 0,
 
             log: false,
@@ -45,6 +57,13 @@ impl Cpu {
             cartrige_type: 0x00,
 
             halted: false,
+            halt_bug: false,
+
+            stopped: false,
+
+            locked: false,
+
+            frame_end: 0,
         }
     }
 
@@ -53,14 +72,6 @@ impl Cpu {
     }
 
     pub fn update_ime(&mut self) {
-        self.di = match self.di {
-            2 => 1,
-            1 => {
-                self.ime = false;
-                0
-            }
-            _ => 0,
-        };
         self.ei = match self.ei {
             2 => 1,
             1 => {
@@ -71,23 +82,51 @@ impl Cpu {
         };
     }
 
-    pub fn test_draw(&mut self) {
-      self.bus.gui.test_draw();
+    // Returns true once per completed frame.
+    pub fn take_frame(&mut self) -> bool {
+      ::std::mem::replace(&mut self.bus.gui.frame_ready, false)
     }
 
-    pub fn get_data_gui(&self, x: usize, y: usize) -> u32 {
-      self.bus.gui.get_data(x, y)
+    pub fn copy_frame(&self, buf: &mut [u8]) {
+      self.bus.gui.copy_frame(buf)
     }
 
     pub fn enable_log(&mut self) {
       self.log = true;
     }
 
+    pub fn save(&self) {
+      self.bus.save();
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+      self.bus.set_button(button, pressed);
+    }
+
+    pub fn rumble_event(&mut self) -> Option<bool> {
+      self.bus.rumble_event()
+    }
+
+    pub fn enable_sound_output(&mut self) {
+      self.bus.sound.output = true;
+    }
+
+    pub fn drain_samples(&mut self, buffer: &mut Vec<f32>) {
+      self.bus.sound.drain_samples(buffer);
+    }
+
     fn update_register_f(&mut self) {
         self.register.f = (self.register.flag.z << 7) | (self.register.flag.h << 5)
             | (self.register.flag.n << 6) | (self.register.flag.c << 4);
     }
 
+    fn update_flag(&mut self) {
+        self.register.flag.z = (self.register.f >> 7) & 0b1;
+        self.register.flag.n = (self.register.f >> 6) & 0b1;
+        self.register.flag.h = (self.register.f >> 5) & 0b1;
+        self.register.flag.c = (self.register.f >> 4) & 0b1;
+    }
+
     fn push_stack(&mut self, value: u16) {
         self.sp = self.sp.wrapping_sub(2);
         self.bus.store16(self.sp, value);
@@ -95,15 +134,126 @@ impl Cpu {
 
     fn pop_stack(&mut self) -> u16 {
         let lhs = self.bus.load(self.sp) as u16;
-        let rhs = self.bus.load(self.sp + 1) as u16;
+        let rhs = self.bus.load(self.sp.wrapping_add(1)) as u16;
 
-        let instruction = ((lhs << 8) | rhs) as u16;
+        let instruction = ((rhs << 8) | lhs) as u16;
 
         self.sp = self.sp.wrapping_add(2);
         instruction
     }
 
-    pub fn run_next_instruction(&mut self, callback: bool) {
+    // Interrupt dispatch takes 5 M-cycles: two wait states, two pushes and the jump.
+    fn handle_interrupt(&mut self) -> bool {
+        if !self.ime || self.bus.pending_interrupt().is_none() {
+            return false;
+        }
+
+        self.ime = false;
+        self.bus.add_to_clock(8);
+
+        let pc = self.pc;
+
+        self.sp = self.sp.wrapping_sub(1);
+        self.bus.store(self.sp, (pc >> 8) as u8);
+        self.bus.add_to_clock(4);
+
+        // The high byte push may land on IE (SP = 0x0000) and change or cancel the request,
+        // so the interrupt is only chosen now. A cancelled dispatch jumps to 0x0000.
+        let interrupt = self.bus.pending_interrupt();
+
+        self.sp = self.sp.wrapping_sub(1);
+        self.bus.store(self.sp, pc as u8);
+        self.bus.add_to_clock(4);
+
+        self.pc = match interrupt {
+            Some(interrupt) => {
+                self.bus.acknowledge_interrupt(interrupt);
+                interrupt.vector()
+            }
+            None => 0x0000,
+        };
+
+        if self.log {
+            println!("| INT |: {:?} -> {:#06X}", interrupt, self.pc);
+        }
+
+        self.bus.add_to_clock(4);
+
+        true
+    }
+
+    // Runs exactly FRAME_CYCLES past the previous frame boundary, carrying any overshoot into the next frame.
+    // Returns early while stopped so the frontend keeps polling input.
+    pub fn run_frame(&mut self) {
+        self.run_frame_until(|_| false);
+    }
+
+    // Like run_frame, but checks `stop` before every instruction and returns true as soon as it holds.
+    pub fn run_frame_until<F: FnMut(&Cpu) -> bool>(&mut self, mut stop: F) -> bool {
+        self.frame_end += FRAME_CYCLES;
+
+        while self.bus.cycles() < self.frame_end {
+            if stop(self) {
+                return true;
+            }
+
+            if self.stopped && !self.bus.is_joypad_pressed() {
+                self.frame_end = self.bus.cycles();
+                return false;
+            }
+
+            self.update_ime();
+            self.run_next_instruction();
+        }
+
+        false
+    }
+
+    // STOP without a pressed key or an illegal opcode: nothing will ever run again without input.
+    pub fn is_stuck(&self) -> bool {
+      self.locked || (self.stopped && !self.bus.is_joypad_pressed())
+    }
+
+    // Address of the next instruction to execute.
+    pub fn next_pc(&self) -> u16 {
+      self.pc
+    }
+
+    pub fn load(&self, addr: u16) -> u8 {
+      self.bus.load(addr)
+    }
+
+    pub fn serial_output(&self) -> &[u8] {
+      self.bus.serial_output()
+    }
+
+    pub fn run_next_instruction(&mut self) {
+        if self.locked {
+            self.bus.add_to_clock(4);
+            return;
+        }
+
+        if self.stopped {
+            // STOP freezes the system clock until a selected joypad line goes low.
+            if !self.bus.is_joypad_pressed() {
+                return;
+            }
+            self.stopped = false;
+        }
+
+        if self.halted {
+            if self.bus.pending_interrupt().is_none() {
+                self.bus.add_to_clock(4);
+                return;
+            }
+            // Any pending interrupt wakes the CPU, IME only decides whether it is serviced.
+            self.halted = false;
+        }
+
+        if self.handle_interrupt() {
+            return;
+        }
+
         let instruction = self.bus.load(self.pc);
 
         self.current_pc = self.pc;
@@ -177,29 +327,46 @@ impl Cpu {
           println!(" ");
           println!(" ");
 
-          println!("| TYPE 0147 |: {:#06X}", self.bus.load(0x0147));
+          let header = self.bus.header();
+
+          println!("| TITLE |: {} {}", header.title, header.manufacturer_code.as_ref().map_or("", |c| c.as_str()));
+          println!(" ");
+
+          println!("| LICENSEE |: {:#06X} {}", header.old_licensee, header.new_licensee);
+          println!(" ");
+
+          println!("| CGB 0143 |: {:#06X} supported: {} only: {} | SGB |: {}", header.cgb_flag, header.supports_cgb(), header.cgb_only(), header.sgb_flag);
+          println!(" ");
+
+          println!("| VERSION 014C |: {:#06X}", header.version);
+          println!(" ");
+
+          println!("| CHECKSUM 014D |: {:#06X} | GLOBAL 014E |: {:#06X} | LOGO |: {}", header.header_checksum, header.global_checksum, header.logo_valid);
+          println!(" ");
+
+          println!("| TYPE 0147 |: {:#06X} {:?}", header.cartridge_type.code(), header.cartridge_type);
           println!(" ");
 
-          let rom_size = self.bus.load(0x0148);
-          println!("| ROM 0148 |: {:#06X}", rom_size);
+          println!("| ROM 0148 |: {:#06X}", header.rom_size);
           println!(" ");
 
-          println!("| RAM 0149 |: {:#06X}", self.bus.load(0x0149));
+          println!("| RAM 0149 |: {:#06X}", header.ram_size);
           println!(" ");
 
-          println!("| BANKS |: {}", self.bus.get_banks_count(rom_size));
+          println!("| BANKS |: {}", header.rom_banks());
           println!(" ");
           println!("***********************************");
         }
 
-        self.pc = self.pc.wrapping_add(1);
-
-        if callback {
-            self.decode_callback(instruction);
+        // HALT bug: the byte after HALT is fetched twice because PC fails to increment.
+        if self.halt_bug {
+            self.halt_bug = false;
         } else {
-            self.decode(instruction);        
+            self.pc = self.pc.wrapping_add(1);
         }
 
+        self.decode(instruction);
+
     }
 
     pub fn power_up(&mut self) {
@@ -247,1458 +414,2264 @@ impl Cpu {
     }
 
     fn decode_callback(&mut self, instruction: u8) {
-        let nn = (self.bus.load(self.current_pc + 2) as u16) << 8
-            | self.bus.load(self.current_pc + 1) as u16;
-        let n = self.bus.load(self.current_pc + 1);
-
         match instruction {
-            0x30 => {
+            0x00 => {
                 let value = self.register.b;
-                self.register.b = self.swap(value, 8);
+                self.register.b = self.rlc(value, 8);
             }
-            0x31 => {
+            0x01 => {
                 let value = self.register.c;
-                self.register.c = self.swap(value, 8);
+                self.register.c = self.rlc(value, 8);
             }
-            0x32 => {
+            0x02 => {
                 let value = self.register.d;
-                self.register.d = self.swap(value, 8);
+                self.register.d = self.rlc(value, 8);
             }
-            0x33 => {
+            0x03 => {
                 let value = self.register.e;
-                self.register.e = self.swap(value, 8);
+                self.register.e = self.rlc(value, 8);
             }
-            0x34 => {
+            0x04 => {
                 let value = self.register.h;
-                self.register.h = self.swap(value, 8);
+                self.register.h = self.rlc(value, 8);
             }
-            0x35 => {
+            0x05 => {
                 let value = self.register.l;
-                self.register.l = self.swap(value, 8);
+                self.register.l = self.rlc(value, 8);
             }
-            0x36 => {
+            0x06 => {
                 let value = self.bus.load(self.register.hl());
-                let res = self.swap(value, 16);
-
+                let res = self.rlc(value, 16);
                 self.bus.store(self.register.hl(), res);
             }
-            0x37 => {
-                let value = self.register.a;
-                self.register.a = self.swap(value, 8);
-            }
-            0x80 => {
-                self.register.b = self.register.b & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            0x81 => {
-                self.register.c = self.register.c & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            0x82 => {
-                self.register.d = self.register.d & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            0x83 => {
-                self.register.e = self.register.e & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            0x84 => {
-                self.register.h = self.register.h & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            0x85 => {
-                self.register.l = self.register.l & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            0x86 => {
-                let value = self.bus.load(self.register.hl());
-                self.bus.store(self.register.hl(), value & !(1 << 0));
-
-                self.bus.add_to_clock(16);
-            }
-            0x87 => {
-                self.register.a = self.register.a & !(1 << 0);
-                self.bus.add_to_clock(8);
-            }
-            _ => panic!("Unknown callback instruction {:#04x}", instruction),
-        }
-    }
-
-    fn decode(&mut self, instruction: u8) {
-        let nn = (self.bus.load(self.current_pc + 2) as u16) << 8
-            | self.bus.load(self.current_pc + 1) as u16;
-        let n = self.bus.load(self.current_pc + 1);
-
-        match instruction {
-            0x00 => self.bus.add_to_clock(4),
-            0x01 => {
-                self.register.set_bc(nn);
-                self.bus.add_to_clock(12);
-            }
-            0x02 => {
-                self.bus.store(self.register.bc(), self.register.a);
-                self.bus.add_to_clock(8);
-            }
-            0x03 => {
-                let value = self.bus.load(self.register.bc()).wrapping_add(1);
-                self.bus.store(self.register.bc(), value);
-
-                self.bus.add_to_clock(8);
-            }
-            0x04 => {
-                let value = self.register.b;
-                self.register.b = self.inc(value, 4);
-            }
-            0x05 => {
-                let value = self.register.b;
-                self.register.b = self.dec(value, 4);
-            }
-            0x06 => {
-                self.register.b = n;
-                self.bus.add_to_clock(8);
-            }
             0x07 => {
-                let old_bit = (self.register.a >> 7) & 0b1;
-                self.register.a = self.register.a.rotate_left(1);
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = old_bit;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.register.a = self.rlc(value, 8);
             }
             0x08 => {
-                self.bus.store16(nn, self.sp);
-                self.bus.add_to_clock(20);
+                let value = self.register.b;
+                self.register.b = self.rrc(value, 8);
             }
             0x09 => {
-                let value = self.bus.load(self.register.bc()) as u16;
-                let res = self.add16(value, 8);
-                self.bus.store16(self.register.hl(), res);
+                let value = self.register.c;
+                self.register.c = self.rrc(value, 8);
             }
             0x0A => {
-                let value = self.bus.load(self.register.bc());
-                self.register.a = value;
-                self.bus.add_to_clock(8);
+                let value = self.register.d;
+                self.register.d = self.rrc(value, 8);
             }
             0x0B => {
-                let mut value = self.bus.load(self.register.bc());
-                value = value.wrapping_sub(1);
-                self.bus.store(self.register.bc(), value);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.rrc(value, 8);
             }
             0x0C => {
-                let value = self.register.c;
-                self.register.c = self.inc(value, 4);
+                let value = self.register.h;
+                self.register.h = self.rrc(value, 8);
             }
             0x0D => {
-                let value = self.register.c;
-                self.register.c = self.dec(value, 4);
+                let value = self.register.l;
+                self.register.l = self.rrc(value, 8);
             }
             0x0E => {
-                self.register.c = n;
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                let res = self.rrc(value, 16);
+                self.bus.store(self.register.hl(), res);
             }
             0x0F => {
-                let old_bit = self.register.a & 0b1;
-                self.register.a = self.register.a.rotate_right(1);
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = old_bit;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.register.a = self.rrc(value, 8);
             }
             0x10 => {
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.register.b = self.rl(value, 8);
             }
             0x11 => {
-                self.register.set_de(nn);
-                self.bus.add_to_clock(12);
+                let value = self.register.c;
+                self.register.c = self.rl(value, 8);
             }
             0x12 => {
-                self.bus.store(self.register.de(), self.register.a);
-                self.bus.add_to_clock(8);
+                let value = self.register.d;
+                self.register.d = self.rl(value, 8);
             }
             0x13 => {
-                let value = self.bus.load(self.register.de()).wrapping_add(1);
-                self.bus.store(self.register.de(), value);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.rl(value, 8);
             }
             0x14 => {
-                let value = self.register.d;
-                self.register.d = self.inc(value, 4);
+                let value = self.register.h;
+                self.register.h = self.rl(value, 8);
             }
             0x15 => {
-                let value = self.register.d;
-                self.register.d = self.dec(value, 4);
+                let value = self.register.l;
+                self.register.l = self.rl(value, 8);
             }
             0x16 => {
-                self.register.d = n;
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                let res = self.rl(value, 16);
+                self.bus.store(self.register.hl(), res);
             }
             0x17 => {
-                // TODO: Probably incorrect.
-                let old_bit = (self.register.a >> 7) & 0b1;
-                self.register.a = self.register.a.rotate_left(self.register.flag.c as u32);
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = old_bit;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.register.a = self.rl(value, 8);
             }
             0x18 => {
-                self.pc = self.pc.wrapping_add(n as u16);
-                self.bus.add_to_clock(8);
+                let value = self.register.b;
+                self.register.b = self.rr(value, 8);
             }
             0x19 => {
-                let value = self.bus.load(self.register.de()) as u16;
-                let res = self.add16(value, 8);
-                self.bus.store16(self.register.hl(), res);
+                let value = self.register.c;
+                self.register.c = self.rr(value, 8);
             }
             0x1A => {
-                let value = self.bus.load(self.register.de());
-                self.register.a = value;
-                self.bus.add_to_clock(8);
+                let value = self.register.d;
+                self.register.d = self.rr(value, 8);
             }
             0x1B => {
-                let mut value = self.bus.load(self.register.de());
-                value = value.wrapping_sub(1);
-                self.bus.store(self.register.de(), value);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.rr(value, 8);
             }
             0x1C => {
-                let value = self.register.e;
-                self.register.e = self.inc(value, 4);
+                let value = self.register.h;
+                self.register.h = self.rr(value, 8);
             }
             0x1D => {
-                let value = self.register.e;
-                self.register.e = self.dec(value, 4);
+                let value = self.register.l;
+                self.register.l = self.rr(value, 8);
             }
             0x1E => {
-                self.register.e = n;
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                let res = self.rr(value, 16);
+                self.bus.store(self.register.hl(), res);
             }
             0x1F => {
-                let old_bit = self.register.a & 0b1;
-                self.register.a = self.register.a.rotate_right(self.register.c as u32);
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = old_bit;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.register.a = self.rr(value, 8);
             }
             0x20 => {
-                if self.register.flag.z == 0 {
-                    self.pc = self.pc.wrapping_add((n as i8) as u16);
-                }
-                self.bus.add_to_clock(8);
+                let value = self.register.b;
+                self.register.b = self.sla(value, 8);
             }
             0x21 => {
-                self.register.set_hl(nn);
-                self.bus.add_to_clock(12);
+                let value = self.register.c;
+                self.register.c = self.sla(value, 8);
+            }
+            0x22 => {
+                let value = self.register.d;
+                self.register.d = self.sla(value, 8);
             }
             0x23 => {
-                let value = self.bus.load(self.register.hl()).wrapping_add(1);
-                self.bus.store(self.register.hl(), value);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.sla(value, 8);
             }
             0x24 => {
                 let value = self.register.h;
-                self.register.h = self.inc(value, 4);
+                self.register.h = self.sla(value, 8);
             }
             0x25 => {
-                let value = self.register.h;
-                self.register.h = self.dec(value, 4);
+                let value = self.register.l;
+                self.register.l = self.sla(value, 8);
             }
             0x26 => {
-                self.register.h = n;
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                let res = self.sla(value, 16);
+                self.bus.store(self.register.hl(), res);
             }
             0x27 => {
-                // TODO: Probaly incorrect.
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.h = 0;
-                self.register.flag.c = (self.register.a == 1) as u8;;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.register.a = self.sla(value, 8);
             }
             0x28 => {
-                if self.register.flag.z == 1 {
-                    self.pc = self.pc.wrapping_add((n as i8) as u16);
-                }
-                self.bus.add_to_clock(8);
+                let value = self.register.b;
+                self.register.b = self.sra(value, 8);
             }
             0x29 => {
-                let value = self.bus.load(self.register.hl()) as u16;
-                let res = self.add16(value, 8);
-                self.bus.store16(self.register.hl(), res);
+                let value = self.register.c;
+                self.register.c = self.sra(value, 8);
             }
             0x2A => {
-                let mut value = self.bus.load(self.register.hl());
-                self.register.a = value;
-
-                value = value.wrapping_add(1);
-                self.bus.store(self.register.hl(), value);
-                self.bus.add_to_clock(8);
+                let value = self.register.d;
+                self.register.d = self.sra(value, 8);
             }
             0x2B => {
-                let mut value = self.bus.load(self.register.hl());
-                value = value.wrapping_sub(1);
-                self.bus.store(self.register.hl(), value);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.sra(value, 8);
             }
             0x2C => {
-                let value = self.register.l;
-                self.register.l = self.inc(value, 4);
+                let value = self.register.h;
+                self.register.h = self.sra(value, 8);
             }
             0x2D => {
                 let value = self.register.l;
-                self.register.l = self.dec(value, 4);
+                self.register.l = self.sra(value, 8);
             }
             0x2E => {
-                self.register.l = n;
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                let res = self.sra(value, 16);
+                self.bus.store(self.register.hl(), res);
+            }
+            0x2F => {
+                let value = self.register.a;
+                self.register.a = self.sra(value, 8);
             }
             0x30 => {
-                if self.register.flag.c == 0 {
-                    self.pc = self.pc.wrapping_add((n as i8) as u16);
-                }
-                self.bus.add_to_clock(8);
+                let value = self.register.b;
+                self.register.b = self.swap(value, 8);
             }
             0x31 => {
-                self.sp = nn;
-                self.bus.add_to_clock(12);
+                let value = self.register.c;
+                self.register.c = self.swap(value, 8);
             }
             0x32 => {
-                let hl = self.register.hl();
-                self.bus.store(hl, self.register.a);
-                self.bus.add_to_clock(8);
-                self.register.set_hl(hl.wrapping_sub(1));
+                let value = self.register.d;
+                self.register.d = self.swap(value, 8);
             }
             0x33 => {
-                let value = self.sp.wrapping_add(1);
-                self.bus.store16(self.sp, value);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.swap(value, 8);
             }
             0x34 => {
-                let value = self.bus.load(self.register.hl());
-                let res = self.inc(value, 12);
-                self.bus.store(self.register.hl(), res);
+                let value = self.register.h;
+                self.register.h = self.swap(value, 8);
             }
             0x35 => {
+                let value = self.register.l;
+                self.register.l = self.swap(value, 8);
+            }
+            0x36 => {
                 let value = self.bus.load(self.register.hl());
-                let res = self.dec(value, 12);
-
+                let res = self.swap(value, 16);
                 self.bus.store(self.register.hl(), res);
             }
-            0x36 => {
-                self.bus.store(self.register.hl(), n);
-                self.bus.add_to_clock(12);
+            0x37 => {
+                let value = self.register.a;
+                self.register.a = self.swap(value, 8);
             }
             0x38 => {
-                if self.register.flag.c == 1 {
-                    self.pc = self.pc.wrapping_add((n as i8) as u16);
-                }
-                self.bus.add_to_clock(8);
+                let value = self.register.b;
+                self.register.b = self.srl(value, 8);
             }
             0x39 => {
-                let value = self.sp;
-                let res = self.add16(value, 8);
-                self.bus.store16(self.register.hl(), res);
+                let value = self.register.c;
+                self.register.c = self.srl(value, 8);
             }
             0x3A => {
-                let value = self.bus.load(self.register.hl());
-                self.register.a = value;
-                self.bus.store(self.register.hl(), value.wrapping_sub(1));
-                self.bus.add_to_clock(8);
+                let value = self.register.d;
+                self.register.d = self.srl(value, 8);
             }
             0x3B => {
-                self.sp = self.sp.wrapping_sub(1);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.register.e = self.srl(value, 8);
             }
             0x3C => {
-                let value = self.register.a;
-                self.register.a = self.inc(value, 4);
+                let value = self.register.h;
+                self.register.h = self.srl(value, 8);
             }
             0x3D => {
-                let value = self.register.a;
-                self.register.a = self.dec(value, 4);
+                let value = self.register.l;
+                self.register.l = self.srl(value, 8);
             }
             0x3E => {
-                self.register.a = n;
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                let res = self.srl(value, 16);
+                self.bus.store(self.register.hl(), res);
+            }
+            0x3F => {
+                let value = self.register.a;
+                self.register.a = self.srl(value, 8);
             }
             0x40 => {
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(0, value, 8);
             }
             0x41 => {
-                self.register.b = self.register.c;
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(0, value, 8);
             }
             0x42 => {
-                self.register.b = self.register.d;
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(0, value, 8);
             }
             0x43 => {
-                self.register.b = self.register.e;
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(0, value, 8);
             }
             0x44 => {
-                self.register.b = self.register.h;
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(0, value, 8);
             }
             0x45 => {
-                self.register.b = self.register.l;
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(0, value, 8);
             }
             0x46 => {
-                self.register.b = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(0, value, 12);
             }
             0x47 => {
-                self.register.b = self.register.a;
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(0, value, 8);
             }
             0x48 => {
-                self.register.c = self.register.b;
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(1, value, 8);
             }
             0x49 => {
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(1, value, 8);
             }
             0x4A => {
-                self.register.c = self.register.d;
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(1, value, 8);
             }
             0x4B => {
-                self.register.c = self.register.e;
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(1, value, 8);
             }
             0x4C => {
-                self.register.c = self.register.h;
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(1, value, 8);
             }
             0x4D => {
-                self.register.c = self.register.l;
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(1, value, 8);
             }
             0x4E => {
-                self.register.c = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(1, value, 12);
             }
             0x4F => {
-                self.register.c = self.register.a;
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(1, value, 8);
             }
             0x50 => {
-                self.register.d = self.register.b;
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(2, value, 8);
             }
             0x51 => {
-                self.register.d = self.register.c;
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(2, value, 8);
             }
             0x52 => {
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(2, value, 8);
             }
             0x53 => {
-                self.register.d = self.register.e;
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(2, value, 8);
             }
             0x54 => {
-                self.register.d = self.register.h;
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(2, value, 8);
             }
             0x55 => {
-                self.register.d = self.register.l;
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(2, value, 8);
             }
             0x56 => {
-                self.register.d = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(2, value, 12);
             }
             0x57 => {
-                self.register.d = self.register.a;
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(2, value, 8);
             }
             0x58 => {
-                self.register.e = self.register.b;
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(3, value, 8);
             }
             0x59 => {
-                self.register.e = self.register.c;
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(3, value, 8);
             }
             0x5A => {
-                self.register.e = self.register.d;
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(3, value, 8);
             }
             0x5B => {
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(3, value, 8);
             }
             0x5C => {
-                self.register.e = self.register.h;
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(3, value, 8);
             }
             0x5D => {
-                self.register.e = self.register.l;
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(3, value, 8);
             }
             0x5E => {
-                self.register.e = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(3, value, 12);
             }
             0x5F => {
-                self.register.e = self.register.a;
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(3, value, 8);
             }
             0x60 => {
-                self.register.h = self.register.b;
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(4, value, 8);
             }
             0x61 => {
-                self.register.h = self.register.c;
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(4, value, 8);
             }
             0x62 => {
-                self.register.h = self.register.d;
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(4, value, 8);
             }
             0x63 => {
-                self.register.h = self.register.e;
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(4, value, 8);
             }
             0x64 => {
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(4, value, 8);
             }
             0x65 => {
-                self.register.h = self.register.l;
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(4, value, 8);
             }
             0x66 => {
-                self.register.h = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(4, value, 12);
             }
             0x67 => {
-                self.register.h = self.register.a;
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(4, value, 8);
             }
             0x68 => {
-                self.register.l = self.register.b;
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(5, value, 8);
             }
             0x69 => {
-                self.register.l = self.register.c;
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(5, value, 8);
             }
             0x6A => {
-                self.register.l = self.register.d;
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(5, value, 8);
             }
             0x6B => {
-                self.register.l = self.register.e;
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(5, value, 8);
             }
             0x6C => {
-                self.register.l = self.register.h;
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(5, value, 8);
             }
             0x6D => {
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(5, value, 8);
             }
             0x6E => {
-                self.register.l = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(5, value, 12);
             }
             0x6F => {
-                self.register.l = self.register.a;
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(5, value, 8);
             }
             0x70 => {
-                self.bus.store(self.register.hl(), self.register.b);
-                self.bus.add_to_clock(8);
+                let value = self.register.b;
+                self.bit(6, value, 8);
             }
             0x71 => {
-                self.bus.store(self.register.hl(), self.register.c);
-                self.bus.add_to_clock(8);
+                let value = self.register.c;
+                self.bit(6, value, 8);
             }
             0x72 => {
-                self.bus.store(self.register.hl(), self.register.d);
-                self.bus.add_to_clock(8);
+                let value = self.register.d;
+                self.bit(6, value, 8);
             }
             0x73 => {
-                self.bus.store(self.register.hl(), self.register.e);
-                self.bus.add_to_clock(8);
+                let value = self.register.e;
+                self.bit(6, value, 8);
             }
             0x74 => {
-                self.bus.store(self.register.hl(), self.register.h);
-                self.bus.add_to_clock(8);
+                let value = self.register.h;
+                self.bit(6, value, 8);
             }
             0x75 => {
-                self.bus.store(self.register.hl(), self.register.l);
-                self.bus.add_to_clock(8);
+                let value = self.register.l;
+                self.bit(6, value, 8);
             }
             0x76 => {
-                self.halted = true;
-                self.bus.add_to_clock(4);
+                let value = self.bus.load(self.register.hl());
+                self.bit(6, value, 12);
             }
             0x77 => {
-                self.bus.store(self.register.hl(), self.register.a);
-                self.bus.add_to_clock(8);
+                let value = self.register.a;
+                self.bit(6, value, 8);
             }
             0x78 => {
-                self.register.a = self.register.b;
-                self.bus.add_to_clock(4);
+                let value = self.register.b;
+                self.bit(7, value, 8);
             }
             0x79 => {
-                self.register.a = self.register.c;
-                self.bus.add_to_clock(4);
+                let value = self.register.c;
+                self.bit(7, value, 8);
             }
             0x7A => {
-                self.register.a = self.register.d;
-                self.bus.add_to_clock(4);
+                let value = self.register.d;
+                self.bit(7, value, 8);
             }
             0x7B => {
-                self.register.a = self.register.e;
-                self.bus.add_to_clock(4);
+                let value = self.register.e;
+                self.bit(7, value, 8);
             }
             0x7C => {
-                self.register.a = self.register.h;
-                self.bus.add_to_clock(4);
+                let value = self.register.h;
+                self.bit(7, value, 8);
             }
             0x7D => {
-                self.register.a = self.register.l;
-                self.bus.add_to_clock(4);
+                let value = self.register.l;
+                self.bit(7, value, 8);
             }
             0x7E => {
-                self.register.a = self.bus.load(self.register.hl());
-                self.bus.add_to_clock(8);
+                let value = self.bus.load(self.register.hl());
+                self.bit(7, value, 12);
             }
             0x7F => {
-                self.bus.add_to_clock(4);
+                let value = self.register.a;
+                self.bit(7, value, 8);
             }
             0x80 => {
-                let value = self.register.b;
-                self.add8(value, 4);
+                self.register.b = self.register.b & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x81 => {
-                let value = self.register.c;
-                self.add8(value, 4);
+                self.register.c = self.register.c & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x82 => {
-                let value = self.register.d;
-                self.add8(value, 4);
+                self.register.d = self.register.d & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x83 => {
-                let value = self.register.e;
-                self.add8(value, 4);
+                self.register.e = self.register.e & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x84 => {
-                let value = self.register.h;
-                self.add8(value, 4);
+                self.register.h = self.register.h & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x85 => {
-                let value = self.register.l;
-                self.add8(value, 4);
+                self.register.l = self.register.l & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x86 => {
                 let value = self.bus.load(self.register.hl());
-                self.add8(value, 8);
+                self.bus.store(self.register.hl(), value & !(1 << 0));
+
+                self.bus.add_to_clock(16);
             }
             0x87 => {
-                let value = self.register.a;
-                self.add8(value, 4);
+                self.register.a = self.register.a & !(1 << 0);
+                self.bus.add_to_clock(8);
             }
             0x88 => {
-                let value = self.register.b;
-                self.adc(value, 4);
+                self.register.b = self.register.b & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x89 => {
-                let value = self.register.c;
-                self.adc(value, 4);
+                self.register.c = self.register.c & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x8A => {
-                let value = self.register.d;
-                self.adc(value, 4);
+                self.register.d = self.register.d & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x8B => {
-                let value = self.register.e;
-                self.adc(value, 4);
+                self.register.e = self.register.e & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x8C => {
-                let value = self.register.h;
-                self.adc(value, 4);
+                self.register.h = self.register.h & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x8D => {
-                let value = self.register.l;
-                self.adc(value, 4);
+                self.register.l = self.register.l & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x8E => {
                 let value = self.bus.load(self.register.hl());
-                self.adc(value, 8);
+                self.bus.store(self.register.hl(), value & !(1 << 1));
+
+                self.bus.add_to_clock(16);
             }
             0x8F => {
-                let value = self.register.a;
-                self.adc(value, 4);
+                self.register.a = self.register.a & !(1 << 1);
+                self.bus.add_to_clock(8);
             }
             0x90 => {
-                let value = self.register.b;
-                self.sub8(value, 4);
+                self.register.b = self.register.b & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x91 => {
-                let value = self.register.c;
-                self.sub8(value, 4);
+                self.register.c = self.register.c & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x92 => {
-                let value = self.register.d;
-                self.sub8(value, 4);
+                self.register.d = self.register.d & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x93 => {
-                let value = self.register.e;
-                self.sub8(value, 4);
+                self.register.e = self.register.e & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x94 => {
-                let value = self.register.h;
-                self.sub8(value, 4);
+                self.register.h = self.register.h & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x95 => {
-                let value = self.register.l;
-                self.sub8(value, 4);
+                self.register.l = self.register.l & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x96 => {
                 let value = self.bus.load(self.register.hl());
-                self.sub8(value, 8);
+                self.bus.store(self.register.hl(), value & !(1 << 2));
+
+                self.bus.add_to_clock(16);
             }
             0x97 => {
-                let value = self.register.a;
-                self.sub8(value, 4);
+                self.register.a = self.register.a & !(1 << 2);
+                self.bus.add_to_clock(8);
             }
             0x98 => {
-                let value = self.register.b;
-                self.sbc(value, 4);
+                self.register.b = self.register.b & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0x99 => {
-                let value = self.register.c;
-                self.sbc(value, 4);
+                self.register.c = self.register.c & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0x9A => {
-                let value = self.register.d;
-                self.sbc(value, 4);
+                self.register.d = self.register.d & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0x9B => {
-                let value = self.register.e;
-                self.sbc(value, 4);
+                self.register.e = self.register.e & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0x9C => {
-                let value = self.register.h;
-                self.sbc(value, 4);
+                self.register.h = self.register.h & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0x9D => {
-                let value = self.register.l;
-                self.sbc(value, 4);
+                self.register.l = self.register.l & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0x9E => {
                 let value = self.bus.load(self.register.hl());
-                self.sbc(value, 8);
+                self.bus.store(self.register.hl(), value & !(1 << 3));
+
+                self.bus.add_to_clock(16);
             }
             0x9F => {
-                let value = self.register.a;
-                self.sbc(value, 4);
+                self.register.a = self.register.a & !(1 << 3);
+                self.bus.add_to_clock(8);
             }
             0xA0 => {
-                let value = self.register.b;
-                self.and(value, 4);
+                self.register.b = self.register.b & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA1 => {
-                let value = self.register.c;
-                self.and(value, 4);
+                self.register.c = self.register.c & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA2 => {
-                let value = self.register.d;
-                self.and(value, 4);
+                self.register.d = self.register.d & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA3 => {
-                let value = self.register.e;
-                self.and(value, 4);
+                self.register.e = self.register.e & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA4 => {
-                let value = self.register.h;
-                self.and(value, 4);
+                self.register.h = self.register.h & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA5 => {
-                let value = self.register.l;
-                self.and(value, 4);
+                self.register.l = self.register.l & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA6 => {
                 let value = self.bus.load(self.register.hl());
-                self.and(value, 8);
+                self.bus.store(self.register.hl(), value & !(1 << 4));
+
+                self.bus.add_to_clock(16);
             }
             0xA7 => {
-                let value = self.register.a;
-                self.and(value, 4);
+                self.register.a = self.register.a & !(1 << 4);
+                self.bus.add_to_clock(8);
             }
             0xA8 => {
-                self.register.a = self.register.a ^ self.register.b;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.b = self.register.b & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xA9 => {
-                self.register.a = self.register.a ^ self.register.c;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.c = self.register.c & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xAA => {
-                self.register.a = self.register.a ^ self.register.d;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.d = self.register.d & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xAB => {
-                self.register.a = self.register.a ^ self.register.e;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.e = self.register.e & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xAC => {
-                self.register.a = self.register.a ^ self.register.h;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.h = self.register.h & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xAD => {
-                self.register.a = self.register.a ^ self.register.l;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.l = self.register.l & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xAE => {
-                self.register.a = self.register.a ^ self.bus.load(self.register.hl());
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value & !(1 << 5));
 
-                self.bus.add_to_clock(8);
+                self.bus.add_to_clock(16);
             }
             0xAF => {
-                self.register.a = self.register.a ^ self.register.a;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.a = self.register.a & !(1 << 5);
+                self.bus.add_to_clock(8);
             }
             0xB0 => {
-                self.register.a = self.register.a | self.register.b;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.b = self.register.b & !(1 << 6);
+                self.bus.add_to_clock(8);
             }
             0xB1 => {
-                self.register.a = self.register.a | self.register.c;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.c = self.register.c & !(1 << 6);
+                self.bus.add_to_clock(8);
             }
             0xB2 => {
-                self.register.a = self.register.a | self.register.d;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.d = self.register.d & !(1 << 6);
+                self.bus.add_to_clock(8);
             }
             0xB3 => {
-                self.register.a = self.register.a | self.register.e;
-
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
+                self.register.e = self.register.e & !(1 << 6);
+                self.bus.add_to_clock(8);
             }
             0xB4 => {
-                self.register.a = self.register.a | self.register.h;
+                self.register.h = self.register.h & !(1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xB5 => {
+                self.register.l = self.register.l & !(1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xB6 => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value & !(1 << 6));
 
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
+                self.bus.add_to_clock(16);
+            }
+            0xB7 => {
+                self.register.a = self.register.a & !(1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xB8 => {
+                self.register.b = self.register.b & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xB9 => {
+                self.register.c = self.register.c & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xBA => {
+                self.register.d = self.register.d & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xBB => {
+                self.register.e = self.register.e & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xBC => {
+                self.register.h = self.register.h & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xBD => {
+                self.register.l = self.register.l & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xBE => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value & !(1 << 7));
 
-                self.update_register_f();
+                self.bus.add_to_clock(16);
+            }
+            0xBF => {
+                self.register.a = self.register.a & !(1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xC0 => {
+                self.register.b = self.register.b | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC1 => {
+                self.register.c = self.register.c | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC2 => {
+                self.register.d = self.register.d | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC3 => {
+                self.register.e = self.register.e | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC4 => {
+                self.register.h = self.register.h | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC5 => {
+                self.register.l = self.register.l | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC6 => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 0));
 
-                self.bus.add_to_clock(4);
+                self.bus.add_to_clock(16);
             }
-            0xB5 => {
-                self.register.a = self.register.a | self.register.l;
+            0xC7 => {
+                self.register.a = self.register.a | (1 << 0);
+                self.bus.add_to_clock(8);
+            }
+            0xC8 => {
+                self.register.b = self.register.b | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xC9 => {
+                self.register.c = self.register.c | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xCA => {
+                self.register.d = self.register.d | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xCB => {
+                self.register.e = self.register.e | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xCC => {
+                self.register.h = self.register.h | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xCD => {
+                self.register.l = self.register.l | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xCE => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 1));
 
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
+                self.bus.add_to_clock(16);
+            }
+            0xCF => {
+                self.register.a = self.register.a | (1 << 1);
+                self.bus.add_to_clock(8);
+            }
+            0xD0 => {
+                self.register.b = self.register.b | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD1 => {
+                self.register.c = self.register.c | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD2 => {
+                self.register.d = self.register.d | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD3 => {
+                self.register.e = self.register.e | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD4 => {
+                self.register.h = self.register.h | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD5 => {
+                self.register.l = self.register.l | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD6 => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 2));
 
-                self.update_register_f();
+                self.bus.add_to_clock(16);
+            }
+            0xD7 => {
+                self.register.a = self.register.a | (1 << 2);
+                self.bus.add_to_clock(8);
+            }
+            0xD8 => {
+                self.register.b = self.register.b | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xD9 => {
+                self.register.c = self.register.c | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xDA => {
+                self.register.d = self.register.d | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xDB => {
+                self.register.e = self.register.e | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xDC => {
+                self.register.h = self.register.h | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xDD => {
+                self.register.l = self.register.l | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xDE => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 3));
 
-                self.bus.add_to_clock(4);
+                self.bus.add_to_clock(16);
             }
-            0xB6 => {
+            0xDF => {
+                self.register.a = self.register.a | (1 << 3);
+                self.bus.add_to_clock(8);
+            }
+            0xE0 => {
+                self.register.b = self.register.b | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE1 => {
+                self.register.c = self.register.c | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE2 => {
+                self.register.d = self.register.d | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE3 => {
+                self.register.e = self.register.e | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE4 => {
+                self.register.h = self.register.h | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE5 => {
+                self.register.l = self.register.l | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE6 => {
                 let value = self.bus.load(self.register.hl());
-                self.register.a = self.register.a | value;
+                self.bus.store(self.register.hl(), value | (1 << 4));
 
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
+                self.bus.add_to_clock(16);
+            }
+            0xE7 => {
+                self.register.a = self.register.a | (1 << 4);
+                self.bus.add_to_clock(8);
+            }
+            0xE8 => {
+                self.register.b = self.register.b | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xE9 => {
+                self.register.c = self.register.c | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xEA => {
+                self.register.d = self.register.d | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xEB => {
+                self.register.e = self.register.e | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xEC => {
+                self.register.h = self.register.h | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xED => {
+                self.register.l = self.register.l | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xEE => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 5));
 
-                self.update_register_f();
+                self.bus.add_to_clock(16);
+            }
+            0xEF => {
+                self.register.a = self.register.a | (1 << 5);
+                self.bus.add_to_clock(8);
+            }
+            0xF0 => {
+                self.register.b = self.register.b | (1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xF1 => {
+                self.register.c = self.register.c | (1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xF2 => {
+                self.register.d = self.register.d | (1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xF3 => {
+                self.register.e = self.register.e | (1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xF4 => {
+                self.register.h = self.register.h | (1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xF5 => {
+                self.register.l = self.register.l | (1 << 6);
+                self.bus.add_to_clock(8);
+            }
+            0xF6 => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 6));
 
+                self.bus.add_to_clock(16);
+            }
+            0xF7 => {
+                self.register.a = self.register.a | (1 << 6);
                 self.bus.add_to_clock(8);
             }
-            0xB7 => {
-                self.register.a = self.register.a | self.register.a;
+            0xF8 => {
+                self.register.b = self.register.b | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xF9 => {
+                self.register.c = self.register.c | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xFA => {
+                self.register.d = self.register.d | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xFB => {
+                self.register.e = self.register.e | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xFC => {
+                self.register.h = self.register.h | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xFD => {
+                self.register.l = self.register.l | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+            0xFE => {
+                let value = self.bus.load(self.register.hl());
+                self.bus.store(self.register.hl(), value | (1 << 7));
 
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
+                self.bus.add_to_clock(16);
+            }
+            0xFF => {
+                self.register.a = self.register.a | (1 << 7);
+                self.bus.add_to_clock(8);
+            }
+        }
+    }
 
-                self.update_register_f();
+    fn decode(&mut self, instruction: u8) {
+        let nn = (self.bus.load(self.pc.wrapping_add(1)) as u16) << 8
+            | self.bus.load(self.pc) as u16;
+        let n = self.bus.load(self.pc);
 
-                self.bus.add_to_clock(4);
+        match instruction {
+            0x00 => self.bus.add_to_clock(4),
+            0x01 => {
+                self.register.set_bc(nn);
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(12);
+            }
+            0x02 => {
+                self.bus.store(self.register.bc(), self.register.a);
+                self.bus.add_to_clock(8);
+            }
+            0x03 => {
+                let value = self.register.bc().wrapping_add(1);
+                self.register.set_bc(value);
+                self.bus.add_to_clock(8);
+            }
+            0x04 => {
+                let value = self.register.b;
+                self.register.b = self.inc(value, 4);
+            }
+            0x05 => {
+                let value = self.register.b;
+                self.register.b = self.dec(value, 4);
+            }
+            0x06 => {
+                self.register.b = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x07 => {
+                let value = self.register.a;
+                self.register.a = self.rlc(value, 4);
+
+                self.register.flag.z = 0;
+                self.update_register_f();
+            }
+            0x08 => {
+                let value = self.sp;
+                self.bus.store16(nn, value);
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(20);
+            }
+            0x09 => {
+                let value = self.register.bc();
+                let res = self.add16(value, 8);
+                self.register.set_hl(res);
+            }
+            0x0A => {
+                self.register.a = self.bus.load(self.register.bc());
+                self.bus.add_to_clock(8);
+            }
+            0x0B => {
+                let value = self.register.bc().wrapping_sub(1);
+                self.register.set_bc(value);
+                self.bus.add_to_clock(8);
+            }
+            0x0C => {
+                let value = self.register.c;
+                self.register.c = self.inc(value, 4);
+            }
+            0x0D => {
+                let value = self.register.c;
+                self.register.c = self.dec(value, 4);
+            }
+            0x0E => {
+                self.register.c = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x0F => {
+                let value = self.register.a;
+                self.register.a = self.rrc(value, 4);
+
+                self.register.flag.z = 0;
+                self.update_register_f();
+            }
+            0x10 => {
+                // STOP is encoded as 0x10 0x00 and resets DIV.
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.store(0xFF04, 0);
+                self.stopped = true;
+                self.bus.add_to_clock(4);
+            }
+            0x11 => {
+                self.register.set_de(nn);
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(12);
+            }
+            0x12 => {
+                self.bus.store(self.register.de(), self.register.a);
+                self.bus.add_to_clock(8);
+            }
+            0x13 => {
+                let value = self.register.de().wrapping_add(1);
+                self.register.set_de(value);
+                self.bus.add_to_clock(8);
+            }
+            0x14 => {
+                let value = self.register.d;
+                self.register.d = self.inc(value, 4);
+            }
+            0x15 => {
+                let value = self.register.d;
+                self.register.d = self.dec(value, 4);
+            }
+            0x16 => {
+                self.register.d = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x17 => {
+                let value = self.register.a;
+                self.register.a = self.rl(value, 4);
+
+                self.register.flag.z = 0;
+                self.update_register_f();
+            }
+            0x18 => {
+                self.jump_relative(true, n);
+            }
+            0x19 => {
+                let value = self.register.de();
+                let res = self.add16(value, 8);
+                self.register.set_hl(res);
+            }
+            0x1A => {
+                self.register.a = self.bus.load(self.register.de());
+                self.bus.add_to_clock(8);
+            }
+            0x1B => {
+                let value = self.register.de().wrapping_sub(1);
+                self.register.set_de(value);
+                self.bus.add_to_clock(8);
+            }
+            0x1C => {
+                let value = self.register.e;
+                self.register.e = self.inc(value, 4);
+            }
+            0x1D => {
+                let value = self.register.e;
+                self.register.e = self.dec(value, 4);
+            }
+            0x1E => {
+                self.register.e = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x1F => {
+                let value = self.register.a;
+                self.register.a = self.rr(value, 4);
+
+                self.register.flag.z = 0;
+                self.update_register_f();
+            }
+            0x20 => {
+                let condition = self.register.flag.z == 0;
+                self.jump_relative(condition, n);
+            }
+            0x21 => {
+                self.register.set_hl(nn);
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(12);
+            }
+            0x22 => {
+                let hl = self.register.hl();
+                self.bus.store(hl, self.register.a);
+                self.register.set_hl(hl.wrapping_add(1));
+                self.bus.add_to_clock(8);
+            }
+            0x23 => {
+                let value = self.register.hl().wrapping_add(1);
+                self.register.set_hl(value);
+                self.bus.add_to_clock(8);
+            }
+            0x24 => {
+                let value = self.register.h;
+                self.register.h = self.inc(value, 4);
+            }
+            0x25 => {
+                let value = self.register.h;
+                self.register.h = self.dec(value, 4);
+            }
+            0x26 => {
+                self.register.h = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x27 => {
+                self.daa();
+            }
+            0x28 => {
+                let condition = self.register.flag.z == 1;
+                self.jump_relative(condition, n);
+            }
+            0x29 => {
+                let value = self.register.hl();
+                let res = self.add16(value, 8);
+                self.register.set_hl(res);
+            }
+            0x2A => {
+                let hl = self.register.hl();
+                self.register.a = self.bus.load(hl);
+                self.register.set_hl(hl.wrapping_add(1));
+                self.bus.add_to_clock(8);
+            }
+            0x2B => {
+                let value = self.register.hl().wrapping_sub(1);
+                self.register.set_hl(value);
+                self.bus.add_to_clock(8);
+            }
+            0x2C => {
+                let value = self.register.l;
+                self.register.l = self.inc(value, 4);
+            }
+            0x2D => {
+                let value = self.register.l;
+                self.register.l = self.dec(value, 4);
+            }
+            0x2E => {
+                self.register.l = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x2F => {
+                self.register.a = !self.register.a;
+
+                self.register.flag.n = 1;
+                self.register.flag.h = 1;
+
+                self.update_register_f();
+
+                self.bus.add_to_clock(4);
+            }
+            0x30 => {
+                let condition = self.register.flag.c == 0;
+                self.jump_relative(condition, n);
+            }
+            0x31 => {
+                self.sp = nn;
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(12);
+            }
+            0x32 => {
+                let hl = self.register.hl();
+                self.bus.store(hl, self.register.a);
+                self.register.set_hl(hl.wrapping_sub(1));
+                self.bus.add_to_clock(8);
+            }
+            0x33 => {
+                self.sp = self.sp.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x34 => {
+                let value = self.bus.load(self.register.hl());
+                let res = self.inc(value, 12);
+                self.bus.store(self.register.hl(), res);
+            }
+            0x35 => {
+                let value = self.bus.load(self.register.hl());
+                let res = self.dec(value, 12);
+                self.bus.store(self.register.hl(), res);
+            }
+            0x36 => {
+                self.bus.store(self.register.hl(), n);
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(12);
+            }
+            0x37 => {
+                self.register.flag.n = 0;
+                self.register.flag.h = 0;
+                self.register.flag.c = 1;
+
+                self.update_register_f();
+
+                self.bus.add_to_clock(4);
+            }
+            0x38 => {
+                let condition = self.register.flag.c == 1;
+                self.jump_relative(condition, n);
+            }
+            0x39 => {
+                let value = self.sp;
+                let res = self.add16(value, 8);
+                self.register.set_hl(res);
+            }
+            0x3A => {
+                let hl = self.register.hl();
+                self.register.a = self.bus.load(hl);
+                self.register.set_hl(hl.wrapping_sub(1));
+                self.bus.add_to_clock(8);
+            }
+            0x3B => {
+                self.sp = self.sp.wrapping_sub(1);
+                self.bus.add_to_clock(8);
+            }
+            0x3C => {
+                let value = self.register.a;
+                self.register.a = self.inc(value, 4);
+            }
+            0x3D => {
+                let value = self.register.a;
+                self.register.a = self.dec(value, 4);
+            }
+            0x3E => {
+                self.register.a = n;
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(8);
+            }
+            0x3F => {
+                self.register.flag.n = 0;
+                self.register.flag.h = 0;
+                self.register.flag.c ^= 1;
+
+                self.update_register_f();
+
+                self.bus.add_to_clock(4);
+            }
+            0x40 => {
+                self.bus.add_to_clock(4);
+            }
+            0x41 => {
+                self.register.b = self.register.c;
+                self.bus.add_to_clock(4);
+            }
+            0x42 => {
+                self.register.b = self.register.d;
+                self.bus.add_to_clock(4);
+            }
+            0x43 => {
+                self.register.b = self.register.e;
+                self.bus.add_to_clock(4);
+            }
+            0x44 => {
+                self.register.b = self.register.h;
+                self.bus.add_to_clock(4);
+            }
+            0x45 => {
+                self.register.b = self.register.l;
+                self.bus.add_to_clock(4);
+            }
+            0x46 => {
+                self.register.b = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x47 => {
+                self.register.b = self.register.a;
+                self.bus.add_to_clock(4);
+            }
+            0x48 => {
+                self.register.c = self.register.b;
+                self.bus.add_to_clock(4);
+            }
+            0x49 => {
+                self.bus.add_to_clock(4);
+            }
+            0x4A => {
+                self.register.c = self.register.d;
+                self.bus.add_to_clock(4);
+            }
+            0x4B => {
+                self.register.c = self.register.e;
+                self.bus.add_to_clock(4);
+            }
+            0x4C => {
+                self.register.c = self.register.h;
+                self.bus.add_to_clock(4);
+            }
+            0x4D => {
+                self.register.c = self.register.l;
+                self.bus.add_to_clock(4);
+            }
+            0x4E => {
+                self.register.c = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x4F => {
+                self.register.c = self.register.a;
+                self.bus.add_to_clock(4);
+            }
+            0x50 => {
+                self.register.d = self.register.b;
+                self.bus.add_to_clock(4);
+            }
+            0x51 => {
+                self.register.d = self.register.c;
+                self.bus.add_to_clock(4);
+            }
+            0x52 => {
+                self.bus.add_to_clock(4);
+            }
+            0x53 => {
+                self.register.d = self.register.e;
+                self.bus.add_to_clock(4);
+            }
+            0x54 => {
+                self.register.d = self.register.h;
+                self.bus.add_to_clock(4);
+            }
+            0x55 => {
+                self.register.d = self.register.l;
+                self.bus.add_to_clock(4);
+            }
+            0x56 => {
+                self.register.d = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x57 => {
+                self.register.d = self.register.a;
+                self.bus.add_to_clock(4);
+            }
+            0x58 => {
+                self.register.e = self.register.b;
+                self.bus.add_to_clock(4);
+            }
+            0x59 => {
+                self.register.e = self.register.c;
+                self.bus.add_to_clock(4);
+            }
+            0x5A => {
+                self.register.e = self.register.d;
+                self.bus.add_to_clock(4);
+            }
+            0x5B => {
+                self.bus.add_to_clock(4);
+            }
+            0x5C => {
+                self.register.e = self.register.h;
+                self.bus.add_to_clock(4);
+            }
+            0x5D => {
+                self.register.e = self.register.l;
+                self.bus.add_to_clock(4);
+            }
+            0x5E => {
+                self.register.e = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x5F => {
+                self.register.e = self.register.a;
+                self.bus.add_to_clock(4);
+            }
+            0x60 => {
+                self.register.h = self.register.b;
+                self.bus.add_to_clock(4);
+            }
+            0x61 => {
+                self.register.h = self.register.c;
+                self.bus.add_to_clock(4);
+            }
+            0x62 => {
+                self.register.h = self.register.d;
+                self.bus.add_to_clock(4);
+            }
+            0x63 => {
+                self.register.h = self.register.e;
+                self.bus.add_to_clock(4);
+            }
+            0x64 => {
+                self.bus.add_to_clock(4);
+            }
+            0x65 => {
+                self.register.h = self.register.l;
+                self.bus.add_to_clock(4);
+            }
+            0x66 => {
+                self.register.h = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x67 => {
+                self.register.h = self.register.a;
+                self.bus.add_to_clock(4);
+            }
+            0x68 => {
+                self.register.l = self.register.b;
+                self.bus.add_to_clock(4);
+            }
+            0x69 => {
+                self.register.l = self.register.c;
+                self.bus.add_to_clock(4);
+            }
+            0x6A => {
+                self.register.l = self.register.d;
+                self.bus.add_to_clock(4);
+            }
+            0x6B => {
+                self.register.l = self.register.e;
+                self.bus.add_to_clock(4);
+            }
+            0x6C => {
+                self.register.l = self.register.h;
+                self.bus.add_to_clock(4);
+            }
+            0x6D => {
+                self.bus.add_to_clock(4);
+            }
+            0x6E => {
+                self.register.l = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x6F => {
+                self.register.l = self.register.a;
+                self.bus.add_to_clock(4);
+            }
+            0x70 => {
+                self.bus.store(self.register.hl(), self.register.b);
+                self.bus.add_to_clock(8);
+            }
+            0x71 => {
+                self.bus.store(self.register.hl(), self.register.c);
+                self.bus.add_to_clock(8);
+            }
+            0x72 => {
+                self.bus.store(self.register.hl(), self.register.d);
+                self.bus.add_to_clock(8);
+            }
+            0x73 => {
+                self.bus.store(self.register.hl(), self.register.e);
+                self.bus.add_to_clock(8);
+            }
+            0x74 => {
+                self.bus.store(self.register.hl(), self.register.h);
+                self.bus.add_to_clock(8);
+            }
+            0x75 => {
+                self.bus.store(self.register.hl(), self.register.l);
+                self.bus.add_to_clock(8);
+            }
+            0x76 => {
+                if !self.ime && self.bus.pending_interrupt().is_some() {
+                    self.halt_bug = true;
+                } else {
+                    self.halted = true;
+                }
+                self.bus.add_to_clock(4);
+            }
+            0x77 => {
+                self.bus.store(self.register.hl(), self.register.a);
+                self.bus.add_to_clock(8);
+            }
+            0x78 => {
+                self.register.a = self.register.b;
+                self.bus.add_to_clock(4);
+            }
+            0x79 => {
+                self.register.a = self.register.c;
+                self.bus.add_to_clock(4);
+            }
+            0x7A => {
+                self.register.a = self.register.d;
+                self.bus.add_to_clock(4);
+            }
+            0x7B => {
+                self.register.a = self.register.e;
+                self.bus.add_to_clock(4);
+            }
+            0x7C => {
+                self.register.a = self.register.h;
+                self.bus.add_to_clock(4);
+            }
+            0x7D => {
+                self.register.a = self.register.l;
+                self.bus.add_to_clock(4);
+            }
+            0x7E => {
+                self.register.a = self.bus.load(self.register.hl());
+                self.bus.add_to_clock(8);
+            }
+            0x7F => {
+                self.bus.add_to_clock(4);
+            }
+            0x80 => {
+                let value = self.register.b;
+                self.add8(value, 4);
+            }
+            0x81 => {
+                let value = self.register.c;
+                self.add8(value, 4);
+            }
+            0x82 => {
+                let value = self.register.d;
+                self.add8(value, 4);
+            }
+            0x83 => {
+                let value = self.register.e;
+                self.add8(value, 4);
+            }
+            0x84 => {
+                let value = self.register.h;
+                self.add8(value, 4);
+            }
+            0x85 => {
+                let value = self.register.l;
+                self.add8(value, 4);
+            }
+            0x86 => {
+                let value = self.bus.load(self.register.hl());
+                self.add8(value, 8);
+            }
+            0x87 => {
+                let value = self.register.a;
+                self.add8(value, 4);
+            }
+            0x88 => {
+                let value = self.register.b;
+                self.adc(value, 4);
+            }
+            0x89 => {
+                let value = self.register.c;
+                self.adc(value, 4);
+            }
+            0x8A => {
+                let value = self.register.d;
+                self.adc(value, 4);
+            }
+            0x8B => {
+                let value = self.register.e;
+                self.adc(value, 4);
+            }
+            0x8C => {
+                let value = self.register.h;
+                self.adc(value, 4);
+            }
+            0x8D => {
+                let value = self.register.l;
+                self.adc(value, 4);
+            }
+            0x8E => {
+                let value = self.bus.load(self.register.hl());
+                self.adc(value, 8);
+            }
+            0x8F => {
+                let value = self.register.a;
+                self.adc(value, 4);
+            }
+            0x90 => {
+                let value = self.register.b;
+                self.sub8(value, 4);
+            }
+            0x91 => {
+                let value = self.register.c;
+                self.sub8(value, 4);
+            }
+            0x92 => {
+                let value = self.register.d;
+                self.sub8(value, 4);
+            }
+            0x93 => {
+                let value = self.register.e;
+                self.sub8(value, 4);
+            }
+            0x94 => {
+                let value = self.register.h;
+                self.sub8(value, 4);
+            }
+            0x95 => {
+                let value = self.register.l;
+                self.sub8(value, 4);
+            }
+            0x96 => {
+                let value = self.bus.load(self.register.hl());
+                self.sub8(value, 8);
+            }
+            0x97 => {
+                let value = self.register.a;
+                self.sub8(value, 4);
+            }
+            0x98 => {
+                let value = self.register.b;
+                self.sbc(value, 4);
+            }
+            0x99 => {
+                let value = self.register.c;
+                self.sbc(value, 4);
+            }
+            0x9A => {
+                let value = self.register.d;
+                self.sbc(value, 4);
+            }
+            0x9B => {
+                let value = self.register.e;
+                self.sbc(value, 4);
+            }
+            0x9C => {
+                let value = self.register.h;
+                self.sbc(value, 4);
+            }
+            0x9D => {
+                let value = self.register.l;
+                self.sbc(value, 4);
+            }
+            0x9E => {
+                let value = self.bus.load(self.register.hl());
+                self.sbc(value, 8);
+            }
+            0x9F => {
+                let value = self.register.a;
+                self.sbc(value, 4);
+            }
+            0xA0 => {
+                let value = self.register.b;
+                self.and(value, 4);
+            }
+            0xA1 => {
+                let value = self.register.c;
+                self.and(value, 4);
+            }
+            0xA2 => {
+                let value = self.register.d;
+                self.and(value, 4);
+            }
+            0xA3 => {
+                let value = self.register.e;
+                self.and(value, 4);
+            }
+            0xA4 => {
+                let value = self.register.h;
+                self.and(value, 4);
+            }
+            0xA5 => {
+                let value = self.register.l;
+                self.and(value, 4);
+            }
+            0xA6 => {
+                let value = self.bus.load(self.register.hl());
+                self.and(value, 8);
+            }
+            0xA7 => {
+                let value = self.register.a;
+                self.and(value, 4);
+            }
+            0xA8 => {
+                let value = self.register.b;
+                self.xor(value, 4);
+            }
+            0xA9 => {
+                let value = self.register.c;
+                self.xor(value, 4);
+            }
+            0xAA => {
+                let value = self.register.d;
+                self.xor(value, 4);
+            }
+            0xAB => {
+                let value = self.register.e;
+                self.xor(value, 4);
+            }
+            0xAC => {
+                let value = self.register.h;
+                self.xor(value, 4);
+            }
+            0xAD => {
+                let value = self.register.l;
+                self.xor(value, 4);
+            }
+            0xAE => {
+                let value = self.bus.load(self.register.hl());
+                self.xor(value, 8);
+            }
+            0xAF => {
+                let value = self.register.a;
+                self.xor(value, 4);
+            }
+            0xB0 => {
+                let value = self.register.b;
+                self.or(value, 4);
+            }
+            0xB1 => {
+                let value = self.register.c;
+                self.or(value, 4);
+            }
+            0xB2 => {
+                let value = self.register.d;
+                self.or(value, 4);
+            }
+            0xB3 => {
+                let value = self.register.e;
+                self.or(value, 4);
+            }
+            0xB4 => {
+                let value = self.register.h;
+                self.or(value, 4);
+            }
+            0xB5 => {
+                let value = self.register.l;
+                self.or(value, 4);
+            }
+            0xB6 => {
+                let value = self.bus.load(self.register.hl());
+                self.or(value, 8);
+            }
+            0xB7 => {
+                let value = self.register.a;
+                self.or(value, 4);
             }
             0xB8 => {
                 let value = self.register.b;
-
-                let a = self.register.a.wrapping_sub(value);
-
-                let old_value = self.register.a;
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xB9 => {
                 let value = self.register.c;
-
-                let old_value = self.register.a;
-
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xBA => {
                 let value = self.register.d;
-
-                let old_value = self.register.a;
-
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xBB => {
                 let value = self.register.e;
-
-                let old_value = self.register.a;
-
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xBC => {
                 let value = self.register.h;
-
-                let old_value = self.register.a;
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xBD => {
                 let value = self.register.l;
-
-                let old_value = self.register.a;
-
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xBE => {
                 let value = self.bus.load(self.register.hl());
-
-                let old_value = self.register.a;
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(8);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 8);
             }
             0xBF => {
                 let value = self.register.a;
-
-                let old_value = self.register.a;
-
-                let a = self.register.a.wrapping_sub(value);
-
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
-
-                self.update_register_f();
-
-                self.bus.add_to_clock(4);
-                self.pc = self.pc.wrapping_add(1);
+                self.cp(value, 4);
             }
             0xC0 => {
-                if self.register.flag.z == 0 {
-                    self.pop_stack();
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(8);
+                let condition = self.register.flag.z == 0;
+                self.ret(condition);
             }
             0xC1 => {
                 let value = self.pop_stack();
-                self.bus.store16(self.register.bc(), value);
+                self.register.set_bc(value);
                 self.bus.add_to_clock(12);
             }
             0xC2 => {
-                if self.register.flag.c == 0 {
-                    self.pc = nn;
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                let condition = self.register.flag.z == 0;
+                self.jump(condition, nn);
             }
             0xC3 => {
-                self.pc = nn;
-                self.bus.add_to_clock(12);
+                self.jump(true, nn);
             }
             0xC4 => {
-                if self.register.flag.z == 0 {
-                    let addr = self.current_pc + 2;
-                    self.push_stack(addr);
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                let condition = self.register.flag.z == 0;
+                self.call(condition, nn);
+            }
+            0xC5 => {
+                let value = self.register.bc();
+                self.push_stack(value);
+                self.bus.add_to_clock(16);
             }
             0xC6 => {
-                let value = n;
-                self.add8(value, 8);
+                self.pc = self.pc.wrapping_add(1);
+                self.add8(n, 8);
             }
             0xC7 => {
-                let value = self.current_pc;
-                self.push_stack(value);
-
-                self.pc = 0x00;
-
-                self.bus.add_to_clock(32);
+                self.rst(0x00);
             }
             0xC8 => {
-                if self.register.flag.z == 1 {
-                    self.pop_stack();
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(8);
+                let condition = self.register.flag.z == 1;
+                self.ret(condition);
             }
             0xC9 => {
                 self.pc = self.pop_stack();
-
-                self.bus.add_to_clock(8);
+                self.bus.add_to_clock(16);
             }
             0xCA => {
-                if self.register.flag.z == 1 {
-                    self.pc = nn;
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                let condition = self.register.flag.z == 1;
+                self.jump(condition, nn);
             }
             0xCB => {
-                self.run_next_instruction(true);
-            }
-            0xCC => {
-                if self.register.flag.z == 1 {
-                    let addr = self.current_pc + 2;
-                    self.push_stack(addr);
+                if self.log {
+                    println!("| CB |: {:#04x}", n);
+                    println!(" ");
                 }
+
                 self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                self.decode_callback(n);
+            }
+            0xCC => {
+                let condition = self.register.flag.z == 1;
+                self.call(condition, nn);
             }
             0xCD => {
-                let addr = self.current_pc + 2;
-                self.push_stack(addr);
-                self.pc = nn;
-                self.bus.add_to_clock(12);
+                self.call(true, nn);
             }
             0xCE => {
-                let value = n;
-                self.adc(value, 8);
+                self.pc = self.pc.wrapping_add(1);
+                self.adc(n, 8);
             }
             0xCF => {
-                let value = self.current_pc;
-                self.push_stack(value);
-
-                self.pc = 0x08;
-
-                self.bus.add_to_clock(32);
+                self.rst(0x08);
             }
             0xD0 => {
-                if self.register.flag.c == 0 {
-                    self.pop_stack();
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(8);
+                let condition = self.register.flag.c == 0;
+                self.ret(condition);
             }
             0xD1 => {
                 let value = self.pop_stack();
-                self.bus.store16(self.register.de(), value);
+                self.register.set_de(value);
                 self.bus.add_to_clock(12);
             }
             0xD2 => {
-                if self.register.flag.c == 0 {
-                    self.pc = nn;
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                let condition = self.register.flag.c == 0;
+                self.jump(condition, nn);
             }
             0xD4 => {
-                if self.register.flag.c == 0 {
-                    let addr = self.current_pc + 2;
-                    self.push_stack(addr);
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                let condition = self.register.flag.c == 0;
+                self.call(condition, nn);
+            }
+            0xD5 => {
+                let value = self.register.de();
+                self.push_stack(value);
+                self.bus.add_to_clock(16);
             }
             0xD6 => {
-                let value = n;
-                self.sub8(value, 8);
+                self.pc = self.pc.wrapping_add(1);
+                self.sub8(n, 8);
             }
             0xD7 => {
-                let value = self.current_pc;
-                self.push_stack(value);
-
-                self.pc = 0x10;
-
-                self.bus.add_to_clock(32);
+                self.rst(0x10);
             }
             0xD8 => {
-                if self.register.flag.c == 1 {
-                    self.pop_stack();
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(8);
+                let condition = self.register.flag.c == 1;
+                self.ret(condition);
+            }
+            0xD9 => {
+                self.pc = self.pop_stack();
+                self.ime = true;
+                self.bus.add_to_clock(16);
             }
             0xDA => {
-                if self.register.flag.c == 1 {
-                    self.pc = nn;
-                }
-                self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                let condition = self.register.flag.c == 1;
+                self.jump(condition, nn);
             }
             0xDC => {
-                if self.register.flag.c == 1 {
-                    let addr = self.current_pc + 2;
-                    self.push_stack(addr);
-                }
+                let condition = self.register.flag.c == 1;
+                self.call(condition, nn);
+            }
+            0xDE => {
                 self.pc = self.pc.wrapping_add(1);
-                self.bus.add_to_clock(12);
+                self.sbc(n, 8);
             }
             0xDF => {
-                let value = self.current_pc;
-                self.push_stack(value);
-
-                self.pc = 0x18;
-
-                self.bus.add_to_clock(32);
+                self.rst(0x18);
             }
             0xE0 => {
                 self.bus.store(0xFF00 | n as u16, self.register.a);
-                self.bus.add_to_clock(12);
-
                 self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(12);
             }
             0xE1 => {
                 let value = self.pop_stack();
-                self.bus.store16(self.register.hl(), value);
+                self.register.set_hl(value);
                 self.bus.add_to_clock(12);
             }
             0xE2 => {
-                self.bus
-                    .store(0xFF00 | self.register.c as u16, self.register.a);
+                self.bus.store(0xFF00 | self.register.c as u16, self.register.a);
                 self.bus.add_to_clock(8);
             }
+            0xE5 => {
+                let value = self.register.hl();
+                self.push_stack(value);
+                self.bus.add_to_clock(16);
+            }
             0xE6 => {
-                let value = n;
-                self.and(value, 8);
+                self.pc = self.pc.wrapping_add(1);
+                self.and(n, 8);
             }
             0xE7 => {
-                let value = self.current_pc;
+                self.rst(0x20);
+            }
+            0xE8 => {
+                self.sp = self.add_sp(n);
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(16);
+            }
+            0xE9 => {
+                self.pc = self.register.hl();
+                self.bus.add_to_clock(4);
+            }
+            0xEA => {
+                self.bus.store(nn, self.register.a);
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(16);
+            }
+            0xEE => {
+                self.pc = self.pc.wrapping_add(1);
+                self.xor(n, 8);
+            }
+            0xEF => {
+                self.rst(0x28);
+            }
+            0xF0 => {
+                self.register.a = self.bus.load(0xFF00 | n as u16);
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(12);
+            }
+            0xF1 => {
+                let value = self.pop_stack();
+                self.register.set_af(value & 0xFFF0);
+                self.update_flag();
+                self.bus.add_to_clock(12);
+            }
+            0xF2 => {
+                self.register.a = self.bus.load(0xFF00 | self.register.c as u16);
+                self.bus.add_to_clock(8);
+            }
+            0xF3 => {
+                // DI takes effect immediately and cancels a pending EI.
+                self.ime = false;
+                self.ei = 0;
+                self.bus.add_to_clock(4);
+            }
+            0xF5 => {
+                let value = self.register.af();
                 self.push_stack(value);
+                self.bus.add_to_clock(16);
+            }
+            0xF6 => {
+                self.pc = self.pc.wrapping_add(1);
+                self.or(n, 8);
+            }
+            0xF7 => {
+                self.rst(0x30);
+            }
+            0xF8 => {
+                let value = self.add_sp(n);
+                self.register.set_hl(value);
+                self.pc = self.pc.wrapping_add(1);
+                self.bus.add_to_clock(12);
+            }
+            0xF9 => {
+                self.sp = self.register.hl();
+                self.bus.add_to_clock(8);
+            }
+            0xFA => {
+                self.register.a = self.bus.load(nn);
+                self.pc = self.pc.wrapping_add(2);
+                self.bus.add_to_clock(16);
+            }
+            0xFB => {
+                self.ei = 2; //Execute after after instruction (jump through 2);
+                self.bus.add_to_clock(4);
+            }
+            0xFE => {
+                self.pc = self.pc.wrapping_add(1);
+                self.cp(n, 8);
+            }
+            0xFF => {
+                self.rst(0x38);
+            }
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                // Illegal opcodes hang the CPU until the next reset.
+                if self.log {
+                    println!("Illegal instruction {:#04x} at {:#06x}, CPU locked", instruction, self.current_pc);
+                }
+                self.locked = true;
+                self.bus.add_to_clock(4);
+            }
+        }
+    }
+
+    fn swap(&mut self, param: u8, clock: u16) -> u8 {
+        let res = (param >> 4) | (param << 4);
+
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = 0;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(clock);
+
+        res
+    }
+
+    fn sla(&mut self, param: u8, clock: u16) -> u8 {
+        let res = param << 1;
+
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = (param >> 7) & 0b1;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(clock);
+
+        res
+    }
+
+    fn sra(&mut self, param: u8, clock: u16) -> u8 {
+        let res = (param >> 1) | (param & 0x80);
+
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = param & 0b1;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(clock);
 
-                self.pc = 0x20;
+        res
+    }
 
-                self.bus.add_to_clock(32);
-            }
-            0xE8 => {
-                self.sp = self.sp.wrapping_add(n as u16);
+    fn srl(&mut self, param: u8, clock: u16) -> u8 {
+        let res = param >> 1;
 
-                self.register.flag.z = 0;
-                self.register.flag.n = 0;
-                self.register.flag.h = ((self.sp & 0x000F) > 0x000F) as u8;
-                self.register.flag.c = ((self.sp & 0x00FF) > 0x00FF) as u8;
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = param & 0b1;
 
-                self.update_register_f();
+        self.update_register_f();
 
-                self.bus.add_to_clock(16);
-            }
-            0xEA => {
-                self.bus.store(nn, self.register.a);
-                self.bus.add_to_clock(16);
-            }
-            0xEE => {
-                self.register.a = self.register.a ^ n;
+        self.bus.add_to_clock(clock);
 
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
+        res
+    }
 
-                self.update_register_f();
+    fn bit(&mut self, bit: u8, param: u8, clock: u16) {
+        self.register.flag.z = ((param >> bit) & 0b1 == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 1;
 
-                self.bus.add_to_clock(8);
-            }
-            0xEF => {
-                let value = self.current_pc;
-                self.push_stack(value);
+        self.update_register_f();
 
-                self.pc = 0x28;
+        self.bus.add_to_clock(clock);
+    }
 
-                self.bus.add_to_clock(32);
-            }
-            0xF0 => {
-                self.register.a = self.bus.load(0xFF00 | n as u16);
-                self.bus.add_to_clock(12);
+    fn rlc(&mut self, param: u8, clock: u16) -> u8 {
+        let res = param.rotate_left(1);
 
-                self.pc = self.pc.wrapping_add(1);
-            }
-            0xF1 => {
-                let value = self.pop_stack();
-                self.bus.store16(self.register.af(), value);
-                self.bus.add_to_clock(12);
-            }
-            0xF2 => {
-                let value = self.bus.load(0xFF00 | self.register.c as u16);
-                self.register.a = value;
-                self.bus.add_to_clock(8);
-            }
-            0xF3 => {
-                self.di = 2; //Execute after after instruction (jump through 2);
-                self.bus.add_to_clock(4);
-            }
-            0xF6 => {
-                self.register.a = self.register.a | n;
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = (param >> 7) & 0b1;
 
-                self.register.flag.z = (self.register.a == 0) as u8;
-                self.register.flag.n = 0;
-                self.register.flag.h = 0;
-                self.register.flag.c = 0;
+        self.update_register_f();
 
-                self.update_register_f();
+        self.bus.add_to_clock(clock);
 
-                self.bus.add_to_clock(8);
-            }
-            0xF7 => {
-                let value = self.current_pc;
-                self.push_stack(value);
+        res
+    }
 
-                self.pc = 0x30;
+    fn rrc(&mut self, param: u8, clock: u16) -> u8 {
+        let res = param.rotate_right(1);
 
-                self.bus.add_to_clock(32);
-            }
-            0xF8 => {
-                self.bus.store16(self.register.hl(), self.sp + n as u16);
-                self.bus.add_to_clock(12);
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = param & 0b1;
 
-                self.pc = self.pc.wrapping_add(1);
-            }
-            0xFA => {
-                let value = self.bus.load(nn);
-                self.register.a = value;
-                self.bus.add_to_clock(16);
-            }
-            0xFB => {
-                self.ei = 2; //Execute after after instruction (jump through 2);
-                self.bus.add_to_clock(4);
-            }
-            0xFE => {
-                let value = n;
+        self.update_register_f();
 
-                let old_value = self.register.a;
+        self.bus.add_to_clock(clock);
 
-                let a = self.register.a.wrapping_sub(value);
+        res
+    }
 
-                self.register.flag.z = (a == value) as u8;
-                self.register.flag.n = 1;
-                self.register.flag.h = ((old_value & 0x0F) < (value & 0x0F)) as u8;
-                self.register.flag.c = (old_value < value) as u8;
+    fn rl(&mut self, param: u8, clock: u16) -> u8 {
+        let res = (param << 1) | self.register.flag.c;
 
-                self.update_register_f();
+        self.register.flag.z = (res == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = (param >> 7) & 0b1;
 
-                self.bus.add_to_clock(8);
-                self.pc = self.pc.wrapping_add(1);
-            }
-            0xFF => {
-                let value = self.current_pc;
-                self.push_stack(value);
+        self.update_register_f();
 
-                self.pc = 0x38;
+        self.bus.add_to_clock(clock);
 
-                self.bus.add_to_clock(32);
-            }
-            _ => {
-                println!("self.pc: {:#04x}", self.current_pc);
-                panic!("Unknown instruction {:#04x}", instruction);
-            }
-        }
+        res
     }
 
-    fn swap(&mut self, param: u8, clock: u16) -> u8 {
-        let res = (param >> 4) | (param << 4);
+    fn rr(&mut self, param: u8, clock: u16) -> u8 {
+        let res = (param >> 1) | (self.register.flag.c << 7);
 
-        self.register.flag.z = (param == 0) as u8;
+        self.register.flag.z = (res == 0) as u8;
         self.register.flag.n = 0;
         self.register.flag.h = 0;
-        self.register.flag.c = 0;
+        self.register.flag.c = param & 0b1;
 
         self.update_register_f();
 
@@ -1741,8 +2714,8 @@ impl Cpu {
 
         self.register.flag.z = (self.register.a == 0) as u8;
         self.register.flag.n = 0;
-        self.register.flag.h = ((old_value & 0x7) + 1 > 0x7) as u8;
-        self.register.flag.c = ((old_value & 0x7F) + 1 > 0x7F) as u8;
+        self.register.flag.h = ((old_value & 0x0F) + (param & 0x0F) > 0x0F) as u8;
+        self.register.flag.c = (old_value as u16 + param as u16 > 0xFF) as u8;
 
         self.update_register_f();
 
@@ -1750,12 +2723,12 @@ impl Cpu {
     }
 
     fn add16(&mut self, param: u16, clock: u16) -> u16 {
-        let hl = self.bus.load(self.register.hl()) as u16;
+        let hl = self.register.hl();
         let res = hl.wrapping_add(param);
 
         self.register.flag.n = 0;
-        self.register.flag.h = (((param as u16) & 0x7FF) + 1 > 0x7FF) as u8;
-        self.register.flag.c = (((param as u16) & 0x7FFF) + 1 > 0x7FFF) as u8;
+        self.register.flag.h = ((hl & 0x0FFF) + (param & 0x0FFF) > 0x0FFF) as u8;
+        self.register.flag.c = (hl as u32 + param as u32 > 0xFFFF) as u8;
 
         self.update_register_f();
 
@@ -1764,14 +2737,30 @@ impl Cpu {
         res
     }
 
+    // SP + signed n, shared by ADD SP,n and LD HL,SP+n. Flags come from the unsigned low byte add.
+    fn add_sp(&mut self, param: u8) -> u16 {
+        let sp = self.sp;
+        let res = sp.wrapping_add((param as i8) as u16);
+
+        self.register.flag.z = 0;
+        self.register.flag.n = 0;
+        self.register.flag.h = ((sp & 0x0F) + (param as u16 & 0x0F) > 0x0F) as u8;
+        self.register.flag.c = ((sp & 0xFF) + param as u16 > 0xFF) as u8;
+
+        self.update_register_f();
+
+        res
+    }
+
     fn adc(&mut self, param: u8, clock: u16) {
         let old_value = self.register.a;
-        self.register.a = self.register.a.wrapping_add(param.wrapping_add(self.register.flag.c));
+        let carry = self.register.flag.c;
+        self.register.a = old_value.wrapping_add(param).wrapping_add(carry);
 
         self.register.flag.z = (self.register.a == 0) as u8;
         self.register.flag.n = 0;
-        self.register.flag.h = ((old_value & 0x7) + 1 > 0x7) as u8;
-        self.register.flag.c = ((old_value & 0x7F) + 1 > 0x7F) as u8;
+        self.register.flag.h = ((old_value & 0x0F) + (param & 0x0F) + carry > 0x0F) as u8;
+        self.register.flag.c = (old_value as u16 + param as u16 + carry as u16 > 0xFF) as u8;
 
         self.update_register_f();
 
@@ -1794,12 +2783,13 @@ impl Cpu {
 
     fn sbc(&mut self, param: u8, clock: u16) {
         let old_value = self.register.a;
-        self.register.a = self.register.a.wrapping_sub(param).wrapping_add(self.register.c);
+        let carry = self.register.flag.c;
+        self.register.a = old_value.wrapping_sub(param).wrapping_sub(carry);
 
         self.register.flag.z = (self.register.a == 0) as u8;
         self.register.flag.n = 1;
-        self.register.flag.h = ((old_value & 0x0F) < (param & 0x0F)) as u8;
-        self.register.flag.c = (old_value < param) as u8;
+        self.register.flag.h = ((old_value & 0x0F) < (param & 0x0F) + carry) as u8;
+        self.register.flag.c = ((old_value as u16) < param as u16 + carry as u16) as u8;
 
         self.update_register_f();
 
@@ -1818,4 +2808,634 @@ impl Cpu {
 
         self.bus.add_to_clock(clock);
     }
+
+    fn xor(&mut self, param: u8, clock: u16) {
+        self.register.a = self.register.a ^ param;
+
+        self.register.flag.z = (self.register.a == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = 0;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(clock);
+    }
+
+    fn or(&mut self, param: u8, clock: u16) {
+        self.register.a = self.register.a | param;
+
+        self.register.flag.z = (self.register.a == 0) as u8;
+        self.register.flag.n = 0;
+        self.register.flag.h = 0;
+        self.register.flag.c = 0;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(clock);
+    }
+
+    fn cp(&mut self, param: u8, clock: u16) {
+        let old_value = self.register.a;
+
+        self.register.flag.z = (old_value == param) as u8;
+        self.register.flag.n = 1;
+        self.register.flag.h = ((old_value & 0x0F) < (param & 0x0F)) as u8;
+        self.register.flag.c = (old_value < param) as u8;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(clock);
+    }
+
+    fn daa(&mut self) {
+        let mut value = self.register.a;
+
+        if self.register.flag.n == 0 {
+            if self.register.flag.c == 1 || value > 0x99 {
+                value = value.wrapping_add(0x60);
+                self.register.flag.c = 1;
+            }
+            if self.register.flag.h == 1 || (value & 0x0F) > 0x09 {
+                value = value.wrapping_add(0x06);
+            }
+        } else {
+            if self.register.flag.c == 1 {
+                value = value.wrapping_sub(0x60);
+            }
+            if self.register.flag.h == 1 {
+                value = value.wrapping_sub(0x06);
+            }
+        }
+
+        self.register.a = value;
+
+        self.register.flag.z = (value == 0) as u8;
+        self.register.flag.h = 0;
+
+        self.update_register_f();
+
+        self.bus.add_to_clock(4);
+    }
+
+    fn jump_relative(&mut self, condition: bool, offset: u8) {
+        self.pc = self.pc.wrapping_add(1);
+
+        if condition {
+            self.pc = self.pc.wrapping_add((offset as i8) as u16);
+            self.bus.add_to_clock(12);
+        } else {
+            self.bus.add_to_clock(8);
+        }
+    }
+
+    fn jump(&mut self, condition: bool, addr: u16) {
+        self.pc = self.pc.wrapping_add(2);
+
+        if condition {
+            self.pc = addr;
+            self.bus.add_to_clock(16);
+        } else {
+            self.bus.add_to_clock(12);
+        }
+    }
+
+    fn call(&mut self, condition: bool, addr: u16) {
+        self.pc = self.pc.wrapping_add(2);
+
+        if condition {
+            let value = self.pc;
+            self.push_stack(value);
+            self.pc = addr;
+            self.bus.add_to_clock(24);
+        } else {
+            self.bus.add_to_clock(12);
+        }
+    }
+
+    fn ret(&mut self, condition: bool) {
+        if condition {
+            self.pc = self.pop_stack();
+            self.bus.add_to_clock(20);
+        } else {
+            self.bus.add_to_clock(8);
+        }
+    }
+
+    fn rst(&mut self, addr: u16) {
+        let value = self.pc;
+        self.push_stack(value);
+
+        self.pc = addr;
+
+        self.bus.add_to_clock(16);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bus::Interrupt;
+    use joypad::Button;
+    use mbc::{CartridgeHeader, MBC};
+
+    struct Rom(Vec<u8>);
+
+    impl MBC for Rom {
+        fn readrom(&self, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+
+        fn writerom(&mut self, _address: u16, _value: u8) {}
+
+        fn readram(&self, _address: u16) -> u8 {
+            0xFF
+        }
+
+        fn writeram(&mut self, _address: u16, _value: u8) {}
+    }
+
+    // ROM only cartridge with the program at the 0x0100 entry point.
+    fn with_program(program: &[u8]) -> Cpu {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        let mut cpu = Cpu::new(Bus::new(header, Box::new(Rom(rom))));
+        cpu.sp = 0xD000;
+
+        // IF powers up with VBlank requested.
+        cpu.bus.store(0xFF0F, 0x00);
+        cpu
+    }
+
+    fn set_flags(cpu: &mut Cpu, z: u8, n: u8, h: u8, c: u8) {
+        cpu.register.flag.z = z;
+        cpu.register.flag.n = n;
+        cpu.register.flag.h = h;
+        cpu.register.flag.c = c;
+        cpu.update_register_f();
+    }
+
+    fn flags(cpu: &Cpu) -> (u8, u8, u8, u8) {
+        let flag = &cpu.register.flag;
+        (flag.z, flag.n, flag.h, flag.c)
+    }
+
+    // Runs one instruction like run_frame_until does and returns the clock cycles it took.
+    fn step(cpu: &mut Cpu) -> u64 {
+        let before = cpu.bus.cycles();
+        cpu.update_ime();
+        cpu.run_next_instruction();
+        cpu.bus.cycles() - before
+    }
+
+    #[test]
+    fn daa_after_add() {
+        // ADD A,0x27; DAA
+        let mut cpu = with_program(&[0xC6, 0x27, 0x27]);
+        cpu.register.a = 0x15;
+        step(&mut cpu);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x42);
+        assert_eq!(flags(&cpu), (0, 0, 0, 0));
+
+        // ADD A,0x01; DAA
+        let mut cpu = with_program(&[0xC6, 0x01, 0x27]);
+        cpu.register.a = 0x99;
+        step(&mut cpu);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x00);
+        assert_eq!(flags(&cpu), (1, 0, 0, 1));
+    }
+
+    #[test]
+    fn daa_after_sub() {
+        // SUB 0x15; DAA
+        let mut cpu = with_program(&[0xD6, 0x15, 0x27]);
+        cpu.register.a = 0x42;
+        step(&mut cpu);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x27);
+        assert_eq!(flags(&cpu), (0, 1, 0, 0));
+
+        // Both adjustments with N set keep the carry.
+        let mut cpu = with_program(&[0x27]);
+        cpu.register.a = 0x00;
+        set_flags(&mut cpu, 0, 1, 1, 1);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x9A);
+        assert_eq!(flags(&cpu), (0, 1, 0, 1));
+    }
+
+    #[test]
+    fn add_sp_flags_come_from_the_low_byte() {
+        // ADD SP,1
+        let mut cpu = with_program(&[0xE8, 0x01]);
+        cpu.sp = 0x00FF;
+        set_flags(&mut cpu, 1, 1, 0, 0);
+        assert_eq!(step(&mut cpu), 16);
+        assert_eq!(cpu.sp, 0x0100);
+        assert_eq!(flags(&cpu), (0, 0, 1, 1));
+
+        // ADD SP,-8
+        let mut cpu = with_program(&[0xE8, 0xF8]);
+        cpu.sp = 0x0FF8;
+        step(&mut cpu);
+        assert_eq!(cpu.sp, 0x0FF0);
+        assert_eq!(flags(&cpu), (0, 0, 1, 1));
+
+        // ADD SP,-1 from 0x0000 borrows but the unsigned low byte sum carries nothing.
+        let mut cpu = with_program(&[0xE8, 0xFF]);
+        cpu.sp = 0x0000;
+        step(&mut cpu);
+        assert_eq!(cpu.sp, 0xFFFF);
+        assert_eq!(flags(&cpu), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn ld_hl_sp_offset_flags() {
+        // LD HL,SP+1
+        let mut cpu = with_program(&[0xF8, 0x01]);
+        cpu.sp = 0x000F;
+        set_flags(&mut cpu, 1, 1, 0, 1);
+        assert_eq!(step(&mut cpu), 12);
+        assert_eq!(cpu.register.hl(), 0x0010);
+        assert_eq!(cpu.sp, 0x000F);
+        assert_eq!(flags(&cpu), (0, 0, 1, 0));
+
+        // LD HL,SP-2
+        let mut cpu = with_program(&[0xF8, 0xFE]);
+        cpu.sp = 0xFFF2;
+        step(&mut cpu);
+        assert_eq!(cpu.register.hl(), 0xFFF0);
+        assert_eq!(flags(&cpu), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn adc_half_carry_includes_carry_in() {
+        // ADC A,0x01
+        let mut cpu = with_program(&[0xCE, 0x01]);
+        cpu.register.a = 0x0E;
+        set_flags(&mut cpu, 0, 0, 0, 1);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x10);
+        assert_eq!(flags(&cpu), (0, 0, 1, 0));
+
+        // ADC A,0x00
+        let mut cpu = with_program(&[0xCE, 0x00]);
+        cpu.register.a = 0xFF;
+        set_flags(&mut cpu, 0, 0, 0, 1);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x00);
+        assert_eq!(flags(&cpu), (1, 0, 1, 1));
+    }
+
+    #[test]
+    fn sbc_half_carry_includes_carry_in() {
+        // SBC A,0x0F
+        let mut cpu = with_program(&[0xDE, 0x0F]);
+        cpu.register.a = 0x10;
+        set_flags(&mut cpu, 0, 0, 0, 1);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0x00);
+        assert_eq!(flags(&cpu), (1, 1, 1, 0));
+
+        // SBC A,0x00
+        let mut cpu = with_program(&[0xDE, 0x00]);
+        cpu.register.a = 0x00;
+        set_flags(&mut cpu, 0, 0, 0, 1);
+        step(&mut cpu);
+        assert_eq!(cpu.register.a, 0xFF);
+        assert_eq!(flags(&cpu), (0, 1, 1, 1));
+    }
+
+    #[test]
+    fn accumulator_rotates_clear_zero() {
+        // (opcode, A, carry in, A after, carry out)
+        let cases = [
+            (0x07, 0x00, 0, 0x00, 0), // RLCA
+            (0x07, 0x80, 0, 0x01, 1),
+            (0x17, 0x80, 0, 0x00, 1), // RLA
+            (0x17, 0x00, 1, 0x01, 0),
+            (0x0F, 0x00, 0, 0x00, 0), // RRCA
+            (0x0F, 0x01, 0, 0x80, 1),
+            (0x1F, 0x01, 0, 0x00, 1), // RRA
+            (0x1F, 0x00, 1, 0x80, 0),
+        ];
+
+        for &(opcode, a, carry, result, carry_out) in cases.iter() {
+            let mut cpu = with_program(&[opcode]);
+            cpu.register.a = a;
+            set_flags(&mut cpu, 1, 1, 1, carry);
+
+            assert_eq!(step(&mut cpu), 4, "opcode {:#04X}", opcode);
+            assert_eq!(cpu.register.a, result, "opcode {:#04X}", opcode);
+            assert_eq!(flags(&cpu), (0, 0, 0, carry_out), "opcode {:#04X}", opcode);
+            assert_eq!(cpu.register.f & 0x80, 0, "opcode {:#04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn pop_af_masks_low_nibble() {
+        // POP AF
+        let mut cpu = with_program(&[0xF1]);
+        cpu.bus.store16(0xD000, 0x12FF);
+
+        assert_eq!(step(&mut cpu), 12);
+        assert_eq!(cpu.register.af(), 0x12F0);
+        assert_eq!(flags(&cpu), (1, 1, 1, 1));
+        assert_eq!(cpu.sp, 0xD002);
+    }
+
+    #[test]
+    fn conditional_branch_cycles() {
+        // Condition codes NZ, Z, NC, C. (flag z, flag c) that makes each taken and not taken.
+        let taken = [(0, 0), (1, 0), (0, 0), (0, 1)];
+        let not_taken = [(1, 0), (0, 0), (0, 1), (0, 0)];
+
+        // (first opcode of the NZ/Z/NC/C group, instruction bytes, taken cycles, not taken cycles)
+        let groups = [
+            (0x20, 2, 12, 8),  // JR cc,e
+            (0xC2, 3, 16, 12), // JP cc,nn
+            (0xC4, 3, 24, 12), // CALL cc,nn
+            (0xC0, 1, 20, 8),  // RET cc
+        ];
+
+        for &(base, length, taken_cycles, not_taken_cycles) in groups.iter() {
+            for condition in 0..4 {
+                let opcode = base + condition as u8 * 8;
+
+                for &(flags, expected, branch) in [
+                    (taken[condition], taken_cycles, true),
+                    (not_taken[condition], not_taken_cycles, false),
+                ].iter()
+                {
+                    let mut cpu = with_program(&[opcode, 0x00, 0x02]);
+                    cpu.bus.store16(0xD000, 0x0200);
+                    set_flags(&mut cpu, flags.0, 0, 0, flags.1);
+
+                    assert_eq!(step(&mut cpu), expected, "opcode {:#04X} taken {}", opcode, branch);
+
+                    if !branch {
+                        assert_eq!(cpu.pc, 0x0100 + length, "opcode {:#04X}", opcode);
+                    } else if base == 0x20 {
+                        assert_eq!(cpu.pc, 0x0102, "opcode {:#04X}", opcode);
+                    } else {
+                        assert_eq!(cpu.pc, 0x0200, "opcode {:#04X}", opcode);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interrupt_priority_and_acknowledge() {
+        let mut cpu = with_program(&[0x00]);
+        cpu.ime = true;
+        cpu.bus.store(0xFFFF, 0x1F);
+        cpu.bus.request_interrupt(Interrupt::Serial);
+        cpu.bus.request_interrupt(Interrupt::Timer);
+        cpu.bus.request_interrupt(Interrupt::LcdStat);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0048);
+        assert!(!cpu.ime);
+
+        // Only the serviced request is cleared.
+        assert_eq!(cpu.bus.load(0xFF0F), 0xE0 | 0x04 | 0x08);
+
+        // A request that isn't enabled in IE loses to a lower priority one that is.
+        let mut cpu = with_program(&[0x00]);
+        cpu.ime = true;
+        cpu.bus.store(0xFFFF, 0x04);
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+        cpu.bus.request_interrupt(Interrupt::Timer);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0050);
+        assert_eq!(cpu.bus.load(0xFF0F), 0xE0 | 0x01);
+    }
+
+    #[test]
+    fn interrupt_dispatch_takes_20_cycles() {
+        for (&interrupt, &vector) in Interrupt::all().iter().zip([0x40, 0x48, 0x50, 0x58, 0x60].iter()) {
+            let mut cpu = with_program(&[0x00]);
+            cpu.ime = true;
+            cpu.bus.store(0xFFFF, 0x1F);
+            cpu.bus.request_interrupt(interrupt);
+
+            assert_eq!(step(&mut cpu), 20, "{:?}", interrupt);
+            assert_eq!(cpu.pc, vector, "{:?}", interrupt);
+            assert_eq!(cpu.sp, 0xCFFE, "{:?}", interrupt);
+            assert_eq!(cpu.bus.load(0xCFFE), 0x00, "{:?}", interrupt);
+            assert_eq!(cpu.bus.load(0xCFFF), 0x01, "{:?}", interrupt);
+        }
+    }
+
+    #[test]
+    fn ei_enables_interrupts_after_the_next_instruction() {
+        // EI; NOP
+        let mut cpu = with_program(&[0xFB, 0x00, 0x00]);
+        cpu.bus.store(0xFFFF, 0x01);
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0101);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0102);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0040);
+        assert_eq!(cpu.bus.load(0xCFFE), 0x02);
+
+        // EI; DI never lets the interrupt through.
+        let mut cpu = with_program(&[0xFB, 0xF3, 0x00]);
+        cpu.bus.store(0xFFFF, 0x01);
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+
+        step(&mut cpu);
+        step(&mut cpu);
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0103);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn push_onto_ie_changes_the_dispatched_interrupt() {
+        // The high byte of PC 0x0100 lands on IE and leaves only VBlank enabled.
+        let mut cpu = with_program(&[0x00]);
+        cpu.ime = true;
+        cpu.sp = 0x0000;
+        cpu.bus.store(0xFFFF, 0x04);
+        cpu.bus.request_interrupt(Interrupt::Timer);
+
+        assert_eq!(step(&mut cpu), 20);
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.bus.load(0xFF0F), 0xE0 | 0x04);
+
+        let mut cpu = with_program(&[0x00]);
+        cpu.ime = true;
+        cpu.sp = 0x0000;
+        cpu.bus.store(0xFFFF, 0x04);
+        cpu.bus.request_interrupt(Interrupt::Timer);
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0040);
+        assert_eq!(cpu.bus.load(0xFF0F), 0xE0 | 0x04);
+    }
+
+    #[test]
+    fn halt_wakes_without_servicing_when_ime_is_off() {
+        // HALT; INC B
+        let mut cpu = with_program(&[0x76, 0x04, 0x00]);
+        cpu.bus.store(0xFFFF, 0x04);
+        cpu.register.b = 0;
+
+        step(&mut cpu);
+        assert!(cpu.halted);
+
+        assert_eq!(step(&mut cpu), 4);
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, 0x0101);
+
+        cpu.bus.request_interrupt(Interrupt::Timer);
+        step(&mut cpu);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.pc, 0x0102);
+        assert_eq!(cpu.register.b, 1);
+        assert_eq!(cpu.bus.load(0xFF0F), 0xE0 | 0x04);
+    }
+
+    #[test]
+    fn halt_bug_runs_the_next_byte_twice() {
+        // HALT; INC B with IME off and an interrupt already pending.
+        let mut cpu = with_program(&[0x76, 0x04, 0x00]);
+        cpu.bus.store(0xFFFF, 0x04);
+        cpu.bus.request_interrupt(Interrupt::Timer);
+        cpu.register.b = 0;
+
+        step(&mut cpu);
+        assert!(!cpu.halted);
+
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0101);
+        step(&mut cpu);
+        assert_eq!(cpu.pc, 0x0102);
+        assert_eq!(cpu.register.b, 2);
+    }
+
+    #[test]
+    fn stop_resets_div_and_waits_for_a_selected_button() {
+        // STOP 0; INC B
+        let mut cpu = with_program(&[0x10, 0x00, 0x04]);
+        cpu.bus.add_to_clock(2048);
+        assert_ne!(cpu.bus.load(0xFF04), 0);
+
+        // Only the direction keys are selected.
+        cpu.bus.store(0xFF00, 0x20);
+        cpu.register.b = 0;
+
+        step(&mut cpu);
+        assert!(cpu.stopped);
+        assert_eq!(cpu.pc, 0x0102);
+        assert_eq!(cpu.bus.load(0xFF04), 0);
+
+        assert_eq!(step(&mut cpu), 0);
+        assert!(cpu.is_stuck());
+
+        cpu.set_button(Button::A, true);
+        assert_eq!(step(&mut cpu), 0);
+        assert_eq!(cpu.pc, 0x0102);
+
+        cpu.set_button(Button::Right, true);
+        step(&mut cpu);
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.pc, 0x0103);
+        assert_eq!(cpu.register.b, 1);
+    }
+
+    #[test]
+    fn cb_rotates_and_shifts_carry() {
+        // (CB opcode on B, B, carry in, B after, flags after)
+        let cases = [
+            (0x00, 0x80, 0, 0x01, (0, 0, 0, 1)), // RLC
+            (0x00, 0x00, 1, 0x00, (1, 0, 0, 0)),
+            (0x08, 0x01, 0, 0x80, (0, 0, 0, 1)), // RRC
+            (0x10, 0x80, 0, 0x00, (1, 0, 0, 1)), // RL
+            (0x10, 0x01, 1, 0x03, (0, 0, 0, 0)),
+            (0x18, 0x01, 0, 0x00, (1, 0, 0, 1)), // RR
+            (0x18, 0x00, 1, 0x80, (0, 0, 0, 0)),
+            (0x20, 0x81, 1, 0x02, (0, 0, 0, 1)), // SLA
+            (0x28, 0x81, 0, 0xC0, (0, 0, 0, 1)), // SRA
+            (0x30, 0xF1, 1, 0x1F, (0, 0, 0, 0)), // SWAP
+            (0x38, 0x81, 0, 0x40, (0, 0, 0, 1)), // SRL
+            (0x38, 0x01, 1, 0x00, (1, 0, 0, 1)),
+        ];
+
+        for &(opcode, b, carry, result, expected) in cases.iter() {
+            let mut cpu = with_program(&[0xCB, opcode]);
+            cpu.register.b = b;
+            set_flags(&mut cpu, 0, 1, 1, carry);
+
+            step(&mut cpu);
+            assert_eq!(cpu.register.b, result, "CB {:#04X} on {:#04X}", opcode, b);
+            assert_eq!(flags(&cpu), expected, "CB {:#04X} on {:#04X}", opcode, b);
+        }
+    }
+
+    #[test]
+    fn bit_keeps_carry_and_sets_half_carry() {
+        // BIT 7,A
+        let mut cpu = with_program(&[0xCB, 0x7F]);
+        cpu.register.a = 0x80;
+        set_flags(&mut cpu, 1, 1, 0, 1);
+        step(&mut cpu);
+        assert_eq!(flags(&cpu), (0, 0, 1, 1));
+
+        // BIT 0,A
+        let mut cpu = with_program(&[0xCB, 0x47]);
+        cpu.register.a = 0x80;
+        set_flags(&mut cpu, 0, 1, 0, 0);
+        step(&mut cpu);
+        assert_eq!(flags(&cpu), (1, 0, 1, 0));
+        assert_eq!(cpu.register.a, 0x80);
+    }
+
+    #[test]
+    fn cb_cycles() {
+        for opcode in 0..0x100 {
+            let opcode = opcode as u8;
+
+            // Register operands take 8 cycles, BIT b,(HL) reads once and the rest read and write.
+            let expected = match (opcode & 0x07, opcode) {
+                (6, 0x40 ... 0x7F) => 12,
+                (6, _) => 16,
+                _ => 8,
+            };
+
+            let mut cpu = with_program(&[0xCB, opcode]);
+            cpu.register.set_hl(0xC000);
+
+            assert_eq!(step(&mut cpu), expected, "CB {:#04X}", opcode);
+            assert_eq!(cpu.pc, 0x0102, "CB {:#04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn cb_writes_back_to_hl() {
+        // SET 3,(HL); RES 0,(HL); SWAP (HL)
+        let mut cpu = with_program(&[0xCB, 0xDE, 0xCB, 0x86, 0xCB, 0x36]);
+        cpu.register.set_hl(0xC000);
+        cpu.bus.store(0xC000, 0x01);
+
+        step(&mut cpu);
+        assert_eq!(cpu.bus.load(0xC000), 0x09);
+        step(&mut cpu);
+        assert_eq!(cpu.bus.load(0xC000), 0x08);
+        step(&mut cpu);
+        assert_eq!(cpu.bus.load(0xC000), 0x80);
+    }
 }
@@ -54,7 +54,10 @@ impl Debugger {
             match command {
                 Ok(Command::Step(count)) => self.step(count),
                 Ok(Command::Jump(addr)) => self.jump(addr),
-                Ok(Command::Exit) => break,
+                Ok(Command::Exit) => {
+                    self.cpu.save();
+                    break;
+                }
                 Ok(Command::Repeat) => unreachable!(),
                 Err(ref e) => println!("{}", e),
             }
@@ -66,7 +69,7 @@ impl Debugger {
     pub fn step(&mut self, count: usize) {
         for _ in 0..count {
             self.cpu.update_ime();
-            self.cpu.run_next_instruction(false);
+            self.cpu.run_next_instruction();
         }
     }
 
@@ -74,7 +77,7 @@ impl Debugger {
         panic!("{:x}", addr);
         while self.cpu.get_pc() != addr {
             self.cpu.update_ime();
-            self.cpu.run_next_instruction(false);
+            self.cpu.run_next_instruction();
         }
     }
 }
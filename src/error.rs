@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use zip::result::ZipError;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+
+    Zip(ZipError),
+
+    // Archive has no .gb or .gbc entry.
+    NoRomInArchive,
+
+    // File is shorter than the 0x0150 byte cartridge header.
+    TruncatedRom { size: usize },
+
+    BadHeaderChecksum { expected: u8, actual: u8 },
+
+    UnsupportedMapper(u8),
+
+    UnknownRomSize(u8),
+
+    // File is shorter than the ROM size declared in the header.
+    SizeMismatch { expected: usize, actual: usize },
+
+    BadPatch(&'static str),
+
+    PatchChecksum { kind: &'static str, what: &'static str, expected: u32, actual: u32 },
+
+    // Any failure while reading or applying a patch, so the message names the patch and not the ROM.
+    Patch { path: PathBuf, source: Box<Error> },
+
+    // Save file exists but can't be read. Loading again without it must not save over it.
+    Save { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Zip(ref e) => write!(f, "Zip archive error: {}", e),
+            Error::NoRomInArchive => write!(f, "Archive does not contain a .gb or .gbc file"),
+            Error::TruncatedRom { size } => {
+                write!(f, "ROM is truncated: {} bytes is too small to contain a cartridge header", size)
+            }
+            Error::BadHeaderChecksum { expected, actual } => {
+                write!(f, "Cartridge header checksum is invalid: header says {:#04X}, computed {:#04X}", expected, actual)
+            }
+            Error::UnsupportedMapper(code) => {
+                write!(f, "Unsupported cartridge type {:#04X}", code)
+            }
+            Error::UnknownRomSize(code) => {
+                write!(f, "Unknown ROM size code {:#04X}", code)
+            }
+            Error::SizeMismatch { expected, actual } => {
+                write!(f, "ROM size mismatch: header declares {} bytes, file has {}", expected, actual)
+            }
+            Error::BadPatch(reason) => write!(f, "Invalid patch: {}", reason),
+            Error::PatchChecksum { kind, what, expected, actual } => {
+                write!(f, "{} CRC32 mismatch for the {}: expected {:08X}, computed {:08X}", kind, what, expected, actual)
+            }
+            Error::Patch { ref path, ref source } => write!(f, "Patch {}: {}", path.display(), source),
+            Error::Save { ref path, ref source } => write!(f, "Failed to read save {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Zip(ref e) => Some(e),
+            Error::Patch { ref source, .. } => Some(&**source),
+            Error::Save { ref source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(e: ZipError) -> Error {
+        Error::Zip(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
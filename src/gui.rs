@@ -4,6 +4,20 @@ pub const MAX_LINE: u32 = 10;
 pub const MAX_SPRITE_SIZE: u32 = 16;
 pub const MIN_SPRITE_SIZE: u32 = 8;
 
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const LINE_DOTS: u32 = 456;
+pub const LINES: u8 = 154;
+
+const OAM_SCAN_DOTS: u32 = 80;
+const DRAWING_DOTS: u32 = 172;
+
+pub const MODE_HBLANK: u8 = 0b00;
+pub const MODE_VBLANK: u8 = 0b01;
+pub const MODE_OAM: u8 = 0b10;
+pub const MODE_DRAWING: u8 = 0b11;
+
 #[derive(Clone, Copy)]
 pub enum Color {
     //TODO: Probably we must using A-RGB format where A - 0xFF
@@ -13,6 +27,27 @@ pub enum Color {
     Black = 0b11, // 0x000000
 }
 
+impl Color {
+    pub fn from_shade(shade: u16) -> Color {
+        match shade & 0b11 {
+            0b00 => Color::White,
+            0b01 => Color::LightGray,
+            0b10 => Color::DarkGray,
+            0b11 => Color::Black,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn rgb(&self) -> [u8; 3] {
+        match *self {
+            Color::White => [0xFF, 0xFF, 0xFF],
+            Color::LightGray => [0xAA, 0xAA, 0xAA],
+            Color::DarkGray => [0x55, 0x55, 0x55],
+            Color::Black => [0x00, 0x00, 0x00],
+        }
+    }
+}
+
 // lcdc 91
 // stat 85
 // cnt  28
@@ -22,14 +57,39 @@ pub struct OAM {
     coord_x: u8,
     coord_y: u8,
 
-    priority: u8,
+    tile: u8,
+
+    priority: u8, // 0 - above background; 1 - behind background colors 1-3.
 
     flip_h: bool,
     flip_v: bool,
 
+    pallete: u8, // 0 - OBP0; 1 - OBP1.
+
     size_x: u32,
     size_y: u32,
-} 
+}
+
+impl OAM {
+    pub fn new(data: &[u8], size_y: u32) -> OAM {
+        OAM {
+            coord_y: data[0],
+            coord_x: data[1],
+
+            tile: data[2],
+
+            priority: (data[3] >> 7) & 0b1,
+
+            flip_v: (data[3] >> 6) & 0b1 == 1,
+            flip_h: (data[3] >> 5) & 0b1 == 1,
+
+            pallete: (data[3] >> 4) & 0b1,
+
+            size_x: MIN_SPRITE_SIZE,
+            size_y,
+        }
+    }
+}
 
 pub struct Gui {
     pub data: [[[u8; 3]; 144]; 160],
@@ -55,6 +115,7 @@ pub struct Gui {
     pub scroll_x: u8,
 
     pub lyc: u8,
+    pub lyc_interrupt: u8,
 
     pub color: Color,
 
@@ -77,6 +138,27 @@ pub struct Gui {
 
     pub coincidence: u8, // 1 - LYC == LY
 
+    pub v_blank_interrupt: bool,
+    pub lcd_stat_interrupt: bool,
+
+    // Set when the last visible line has been drawn, cleared by the frontend.
+    pub frame_ready: bool,
+
+    // Accuracy option: lock VRAM in mode 3 and OAM in modes 2 and 3 like real hardware.
+    pub access_blocking: bool,
+
+    dots: u32,
+
+    // Dots counted while the LCD is off, so the frontend still gets a frame every 70224 of them.
+    blank_dots: u32,
+
+    // STAT interrupt fires only on a rising edge of the OR of all enabled sources.
+    stat_line: bool,
+
+    window_line: u8,
+
+    character_data: [u8; 0x1800],
+
     bg_display_data_1: [u8; 1024],
     bg_display_data_2: [u8; 1024],
 }
@@ -89,15 +171,15 @@ impl Gui {
 
             lcd_display: false,
 
-            window_tile_map: 0,
+            window_tile_map: 0x9800,
 
             window_display: false,
 
-            bg_window_tile_map: 0,
+            bg_window_tile_map: 0x8800,
 
-            bg_tile_map: 0,
+            bg_tile_map: 0x9800,
 
-            sprite_size: 0,
+            sprite_size: MIN_SPRITE_SIZE,
 
             sprite_display: false,
 
@@ -107,6 +189,7 @@ impl Gui {
             scroll_x: 0,
 
             lyc: 0,
+            lyc_interrupt: 0,
 
             coincidence: 1,
 
@@ -128,29 +211,61 @@ impl Gui {
 
             line: 0,
 
+            v_blank_interrupt: false,
+            lcd_stat_interrupt: false,
+
+            frame_ready: false,
+
+            access_blocking: false,
+
+            dots: 0,
+
+            blank_dots: 0,
+
+            stat_line: false,
+
+            window_line: 0,
+
+            character_data: [0; 0x1800],
+
             bg_display_data_1: [0; 1024],
             bg_display_data_2: [0; 1024],
         }
     }
 
-    pub fn test_draw(&mut self) {
-        //Initialize. 
-        //It's weird, but without pixel on 0:0, another pixels have not detected status.
-        self.data[0][0][0] = 0xFF;
-        self.data[0][0][1] = 0x00; 
-        self.data[0][0][2] = 0x00;
+    // Copies the framebuffer as row-major RGB24, SCREEN_WIDTH * 3 bytes per row.
+    pub fn copy_frame(&self, buf: &mut [u8]) {
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let offset = (y * SCREEN_WIDTH + x) * 3;
+                buf[offset..offset + 3].copy_from_slice(&self.data[x][y]);
+            }
+        }
+    }
+
+    // LCDC bit 7. The screen shows white while the LCD is off.
+    pub fn set_lcd_display(&mut self, on: bool) {
+        if self.lcd_display && !on {
+            self.data = [[Color::White.rgb(); SCREEN_HEIGHT]; SCREEN_WIDTH];
+        }
+
+        self.lcd_display = on;
+    }
+
+    pub fn is_vram_accessible(&self) -> bool {
+        !self.access_blocking || self.mode_flag != MODE_DRAWING
+    }
 
-        self.data[160/2][144/2][0] = 0xFF;
-        self.data[160/2][144/2][1] = 0x00; 
-        self.data[160/2][144/2][2] = 0x00;
+    pub fn is_oam_accessible(&self) -> bool {
+        !self.access_blocking || (self.mode_flag != MODE_OAM && self.mode_flag != MODE_DRAWING)
     }
 
-    pub fn get_data(&self, x: usize, y: usize) -> u32 {
-        let c1 = self.data[x][y][0] as u32;
-        let c2 = self.data[x][y][1] as u32;
-        let c3 = self.data[x][y][2] as u32;
+    pub fn store_character_data(&mut self, address: u16, value: u8) {
+        self.character_data[address as usize] = value;
+    }
 
-        c1 << 16 | c2 << 8 | c3
+    pub fn load_character_data(&self, address: u16) -> u8 {
+        self.character_data[address as usize]
     }
 
     pub fn store_bg_display_data_1(&mut self, address: u16, value: u8) {
@@ -176,4 +291,231 @@ impl Gui {
     pub fn store_sprite(&mut self, address: u16, value: u8) {
         self.sprite_attrib[address as usize] = value;
     }
+
+    // Advances the LCD by the given number of dots (T-cycles).
+    pub fn step(&mut self, cycles: u16) {
+        if !self.lcd_display {
+            self.dots = 0;
+            self.line = 0;
+            self.mode_flag = MODE_HBLANK;
+            self.stat_line = false;
+
+            self.blank_dots += cycles as u32;
+            if self.blank_dots >= LINE_DOTS * LINES as u32 {
+                self.blank_dots -= LINE_DOTS * LINES as u32;
+                self.frame_ready = true;
+            }
+            return;
+        }
+
+        self.dots += cycles as u32;
+
+        if self.dots >= LINE_DOTS {
+            self.dots -= LINE_DOTS;
+            self.line = (self.line + 1) % LINES;
+
+            if self.line == SCREEN_HEIGHT as u8 {
+                self.v_blank_interrupt = true;
+                self.frame_ready = true;
+            }
+        }
+
+        let mode = if self.line >= SCREEN_HEIGHT as u8 {
+            MODE_VBLANK
+        } else if self.dots < OAM_SCAN_DOTS {
+            MODE_OAM
+        } else if self.dots < OAM_SCAN_DOTS + DRAWING_DOTS {
+            MODE_DRAWING
+        } else {
+            MODE_HBLANK
+        };
+
+        if mode != self.mode_flag && mode == MODE_HBLANK {
+            self.render_scanline();
+        }
+
+        self.mode_flag = mode;
+
+        self.coincidence = (self.line == self.lyc) as u8;
+
+        self.update_stat_line();
+    }
+
+    fn update_stat_line(&mut self) {
+        let line = (self.lyc_interrupt == 1 && self.coincidence == 1)
+            || (self.mode0 == 1 && self.mode_flag == MODE_HBLANK)
+            || (self.mode1 == 1 && self.mode_flag == MODE_VBLANK)
+            || (self.mode2 == 1 && self.mode_flag == MODE_OAM);
+
+        if line && !self.stat_line {
+            self.lcd_stat_interrupt = true;
+        }
+
+        self.stat_line = line;
+    }
+
+    // Draws the current line into the framebuffer, called once per line at the end of pixel transfer.
+    pub fn render_scanline(&mut self) {
+        let y = self.line as usize;
+
+        if y >= SCREEN_HEIGHT {
+            return;
+        }
+
+        if y == 0 {
+            self.window_line = 0;
+        }
+
+        // Colour numbers before palette mapping, sprites need them for the priority check.
+        let mut bg_line = [0u8; SCREEN_WIDTH];
+
+        if self.bg_display {
+            self.render_background(y, &mut bg_line);
+
+            if self.window_display {
+                self.render_window(y, &mut bg_line);
+            }
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            let shade = self.pallete_base[bg_line[x] as usize];
+            self.data[x][y] = Color::from_shade(shade).rgb();
+        }
+
+        if self.sprite_display {
+            self.render_sprites(y, &bg_line);
+        }
+    }
+
+    fn render_background(&self, y: usize, bg_line: &mut [u8; SCREEN_WIDTH]) {
+        let map_y = (y as u8).wrapping_add(self.scroll_y);
+
+        for x in 0..SCREEN_WIDTH {
+            let map_x = (x as u8).wrapping_add(self.scroll_x);
+
+            bg_line[x] = self.map_pixel(self.bg_tile_map, map_x, map_y);
+        }
+    }
+
+    fn render_window(&mut self, y: usize, bg_line: &mut [u8; SCREEN_WIDTH]) {
+        if y < self.window_y as usize || self.window_x > 166 {
+            return;
+        }
+
+        // WX is offset by 7, the window starts at screen column WX - 7.
+        let start = self.window_x as i32 - 7;
+        let map_y = self.window_line;
+
+        for x in 0..SCREEN_WIDTH {
+            if (x as i32) < start {
+                continue;
+            }
+
+            let map_x = (x as i32 - start) as u8;
+
+            bg_line[x] = self.map_pixel(self.window_tile_map, map_x, map_y);
+        }
+
+        // The window keeps its own line counter, so hiding it mid-frame resumes where it left off.
+        self.window_line = self.window_line.wrapping_add(1);
+    }
+
+    fn map_pixel(&self, tile_map: u32, map_x: u8, map_y: u8) -> u8 {
+        let index = (map_y as u16 / 8) * 32 + (map_x as u16 / 8);
+
+        let tile = match tile_map {
+            0x9800 => self.bg_display_data_1[index as usize],
+            0x9C00 => self.bg_display_data_2[index as usize],
+            _ => unreachable!(),
+        };
+
+        let tile_address = match self.bg_window_tile_map {
+            0x8000 => tile as u16 * 16,
+            // 0x8800 addressing uses signed tile numbers relative to 0x9000.
+            0x8800 => (0x1000 as i32 + (tile as i8) as i32 * 16) as u16,
+            _ => unreachable!(),
+        };
+
+        self.tile_pixel(tile_address, map_x % 8, map_y % 8)
+    }
+
+    fn tile_pixel(&self, tile_address: u16, x: u8, y: u8) -> u8 {
+        let address = (tile_address + y as u16 * 2) as usize;
+
+        let low = self.character_data[address];
+        let high = self.character_data[address + 1];
+
+        let bit = 7 - x;
+
+        ((high >> bit) & 0b1) << 1 | ((low >> bit) & 0b1)
+    }
+
+    fn render_sprites(&mut self, y: usize, bg_line: &[u8; SCREEN_WIDTH]) {
+        let mut sprites: Vec<OAM> = Vec::with_capacity(MAX_LINE as usize);
+
+        for i in 0..MAX_SPRITES as usize {
+            let sprite = OAM::new(&self.sprite_attrib[i * 4..i * 4 + 4], self.sprite_size);
+
+            let top = sprite.coord_y as i32 - 16;
+
+            if (y as i32) < top || (y as i32) >= top + sprite.size_y as i32 {
+                continue;
+            }
+
+            sprites.push(sprite);
+
+            if sprites.len() == MAX_LINE as usize {
+                break;
+            }
+        }
+
+        // Lower X wins, ties go to the earlier OAM entry. Draw in reverse so winners end on top.
+        sprites.sort_by_key(|sprite| sprite.coord_x);
+
+        for sprite in sprites.iter().rev() {
+            let mut row = (y as i32 - (sprite.coord_y as i32 - 16)) as u8;
+
+            if sprite.flip_v {
+                row = (sprite.size_y as u8 - 1) - row;
+            }
+
+            // In 8x16 mode the lowest tile bit is ignored.
+            let tile = match sprite.size_y {
+                MAX_SPRITE_SIZE => sprite.tile & 0xFE,
+                _ => sprite.tile,
+            };
+
+            let tile_address = tile as u16 * 16;
+
+            let pallete = match sprite.pallete {
+                0 => self.pallete_0,
+                _ => self.pallete_1,
+            };
+
+            for column in 0..sprite.size_x as u8 {
+                let x = sprite.coord_x as i32 - 8 + column as i32;
+
+                if x < 0 || x >= SCREEN_WIDTH as i32 {
+                    continue;
+                }
+
+                let pixel_x = match sprite.flip_h {
+                    true => 7 - column,
+                    false => column,
+                };
+
+                let color = self.tile_pixel(tile_address, pixel_x, row);
+
+                if color == 0 {
+                    continue;
+                }
+
+                if sprite.priority == 1 && bg_line[x as usize] != 0 {
+                    continue;
+                }
+
+                self.data[x as usize][y] = Color::from_shade(pallete[color as usize]).rgb();
+            }
+        }
+    }
 }
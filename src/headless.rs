@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::io;
+
+use cpu::Cpu;
+use gui::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+// Exit codes of a headless run.
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_TIMEOUT: i32 = 2;
+
+pub enum Condition {
+    // PC is about to execute this address.
+    Pc(u16),
+    // The serial port has sent this text.
+    Serial(String),
+    // Memory at the address holds the value.
+    Memory(u16, u8),
+}
+
+impl Condition {
+    fn holds(&self, cpu: &Cpu) -> bool {
+        match *self {
+            Condition::Pc(addr) => cpu.next_pc() == addr,
+            Condition::Serial(ref text) => {
+                let output = cpu.serial_output();
+                output.len() >= text.len()
+                    && output.windows(text.len()).any(|w| w == text.as_bytes())
+            }
+            Condition::Memory(addr, value) => cpu.load(addr) == value,
+        }
+    }
+}
+
+pub struct Headless {
+    // Frame limit, unlimited when None.
+    pub frames: Option<u64>,
+
+    pub conditions: Vec<Condition>,
+
+    // Binary PPM of the last frame.
+    pub screenshot: Option<String>,
+
+    // Work RAM, 0xC000-0xDFFF as seen by the CPU.
+    pub ram_dump: Option<String>,
+}
+
+impl Headless {
+    pub fn new() -> Headless {
+        Headless {
+            frames: None,
+            conditions: Vec::new(),
+            screenshot: None,
+            ram_dump: None,
+        }
+    }
+
+    // Runs until a condition holds or the frame limit is reached, returns the exit code.
+    pub fn run(&self, cpu: &mut Cpu) -> i32 {
+        let mut frames: u64 = 0;
+        let mut serial_len = 0;
+
+        let mut stuck = false;
+
+        let met = loop {
+            if self.frames.map_or(false, |limit| frames >= limit) {
+                break false;
+            }
+
+            // There is no joypad to leave STOP and no way out of a lock up, give up instead of spinning.
+            if cpu.is_stuck() {
+                stuck = true;
+                break false;
+            }
+
+            let conditions = &self.conditions;
+
+            // Serial conditions only need another look once new output arrived.
+            let stopped = cpu.run_frame_until(|cpu| {
+                let serial_changed = cpu.serial_output().len() != serial_len;
+                serial_len = cpu.serial_output().len();
+
+                conditions.iter().any(|condition| match *condition {
+                    Condition::Serial(_) if !serial_changed => false,
+                    _ => condition.holds(cpu),
+                })
+            });
+
+            frames += 1;
+
+            if stopped {
+                break true;
+            }
+        };
+
+        let output = cpu.serial_output();
+        if !output.is_empty() {
+            println!("{}", String::from_utf8_lossy(output));
+        }
+
+        println!("Ran {} frames", frames);
+
+        if let Some(ref path) = self.screenshot {
+            if let Err(e) = write_screenshot(cpu, path) {
+                println!("Failed to write {}: {}", path, e);
+                return EXIT_ERROR;
+            }
+        }
+
+        if let Some(ref path) = self.ram_dump {
+            if let Err(e) = write_ram_dump(cpu, path) {
+                println!("Failed to write {}: {}", path, e);
+                return EXIT_ERROR;
+            }
+        }
+
+        cpu.save();
+
+        if stuck {
+            println!("CPU stopped or locked up");
+            return EXIT_TIMEOUT;
+        }
+
+        // With only a frame limit, reaching it is the expected outcome.
+        if met || self.conditions.is_empty() {
+            EXIT_SUCCESS
+        } else {
+            println!("Condition not met");
+            EXIT_TIMEOUT
+        }
+    }
+}
+
+// Parses "<addr>=<value>" with hexadecimal numbers, e.g. "C000=01".
+pub fn parse_memory_condition(text: &str) -> Option<Condition> {
+    let mut parts = text.splitn(2, '=');
+    let addr = parse_hex(parts.next()?)?;
+    let value = parse_hex(parts.next()?)?;
+
+    if value > 0xFF {
+        return None;
+    }
+
+    Some(Condition::Memory(addr, value as u8))
+}
+
+pub fn parse_hex(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let text = text.trim_start_matches("0x").trim_start_matches("0X").trim_start_matches('$');
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn write_screenshot(cpu: &Cpu, path: &str) -> io::Result<()> {
+    let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+    cpu.copy_frame(&mut frame);
+
+    let mut file = File::create(path)?;
+    write!(file, "P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT)?;
+    file.write_all(&frame)
+}
+
+fn write_ram_dump(cpu: &Cpu, path: &str) -> io::Result<()> {
+    let ram: Vec<u8> = (0xC000..0xE000).map(|addr| cpu.load(addr as u16)).collect();
+
+    File::create(path)?.write_all(&ram)
+}
@@ -0,0 +1,326 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use sdl2::controller::{Axis, Button as ControllerButton, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::{GameControllerSubsystem, Sdl};
+
+use cpu::Cpu;
+use joypad::Button;
+
+// Bindings file, one binding per line, "#" starts a comment:
+//
+//   key.<button> = <SDL key name>[, <SDL key name>...]
+//   pad.<button> = <SDL controller button>[, ...]
+//   dead_zone = <0-32767>
+//
+// <button> is one of right, left, up, down, a, b, select, start. SDL controller buttons are
+// a, b, x, y, back, guide, start, leftstick, rightstick, leftshoulder, rightshoulder,
+// dpup, dpdown, dpleft, dpright. A line replaces the default bindings of that button.
+// Escape, P, N, M and Tab are emulator hotkeys and cannot be bound.
+
+const HOTKEYS: [Keycode; 5] = [Keycode::Escape, Keycode::P, Keycode::N, Keycode::M, Keycode::Tab];
+
+const BUTTONS: [(&'static str, Button); 8] = [
+    ("right", Button::Right),
+    ("left", Button::Left),
+    ("up", Button::Up),
+    ("down", Button::Down),
+    ("a", Button::A),
+    ("b", Button::B),
+    ("select", Button::Select),
+    ("start", Button::Start),
+];
+
+pub struct Bindings {
+    keys: Vec<(Keycode, Button)>,
+    buttons: Vec<(ControllerButton, Button)>,
+
+    // Analog stick deflection below which the stick is treated as centered.
+    dead_zone: i16,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings {
+            keys: vec![
+                (Keycode::Right, Button::Right),
+                (Keycode::Left, Button::Left),
+                (Keycode::Up, Button::Up),
+                (Keycode::Down, Button::Down),
+                (Keycode::X, Button::A),
+                (Keycode::Z, Button::B),
+                (Keycode::Backspace, Button::Select),
+                (Keycode::Return, Button::Start),
+            ],
+            buttons: vec![
+                (ControllerButton::DPadRight, Button::Right),
+                (ControllerButton::DPadLeft, Button::Left),
+                (ControllerButton::DPadUp, Button::Up),
+                (ControllerButton::DPadDown, Button::Down),
+                (ControllerButton::B, Button::A),
+                (ControllerButton::A, Button::B),
+                (ControllerButton::Back, Button::Select),
+                (ControllerButton::Start, Button::Start),
+            ],
+
+            dead_zone: 8000,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Bindings, String> {
+        let mut text = String::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| format!("{}: {}", path.as_ref().display(), e))?;
+
+        let mut bindings = Bindings::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            bindings
+                .parse_line(line)
+                .map_err(|e| format!("{}:{}: {}", path.as_ref().display(), number + 1, e))?;
+        }
+
+        Ok(bindings)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().ok_or("expected <name> = <value>")?.trim();
+
+        if name == "dead_zone" {
+            self.dead_zone = match value.parse::<i16>() {
+                Ok(dead_zone) if dead_zone >= 0 => dead_zone,
+                _ => return Err(format!("invalid dead zone {}, expected 0-32767", value)),
+            };
+            return Ok(());
+        }
+
+        let mut name = name.splitn(2, '.');
+        let device = name.next().unwrap_or("");
+        let button = name.next().unwrap_or("");
+
+        let button = BUTTONS
+            .iter()
+            .find(|&&(n, _)| n == button)
+            .map(|&(_, b)| b)
+            .ok_or(format!("unknown button {}", button))?;
+
+        let names = value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty());
+
+        match device {
+            "key" => {
+                self.keys.retain(|&(_, b)| b != button);
+                for name in names {
+                    let keycode = Keycode::from_name(name).ok_or(format!("unknown key {}", name))?;
+                    if HOTKEYS.contains(&keycode) {
+                        return Err(format!("key {} is reserved for an emulator hotkey", name));
+                    }
+                    self.keys.push((keycode, button));
+                }
+            }
+            "pad" => {
+                self.buttons.retain(|&(_, b)| b != button);
+                for name in names {
+                    let pad = ControllerButton::from_string(name)
+                        .ok_or(format!("unknown controller button {}", name))?;
+                    self.buttons.push((pad, button));
+                }
+            }
+            _ => return Err(format!("unknown device {}, expected key or pad", device)),
+        }
+
+        Ok(())
+    }
+
+    fn key(&self, keycode: Keycode) -> Option<Button> {
+        self.keys.iter().find(|&&(k, _)| k == keycode).map(|&(_, b)| b)
+    }
+
+    fn button(&self, pad: ControllerButton) -> Option<Button> {
+        self.buttons.iter().find(|&&(p, _)| p == pad).map(|&(_, b)| b)
+    }
+}
+
+pub struct Input {
+    bindings: Bindings,
+
+    subsystem: Option<GameControllerSubsystem>,
+    controllers: Vec<GameController>,
+
+    // Directions currently held by the left stick.
+    stick_x: Option<Button>,
+    stick_y: Option<Button>,
+
+    // Every source holding a button down, a button is released when the last one lets go.
+    held: Vec<(Source, Button)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    Key(Keycode),
+    Pad(i32, ControllerButton),
+    // The left stick, shared by every controller like stick_x and stick_y.
+    Stick,
+}
+
+impl Input {
+    pub fn new(sdl_context: &Sdl, bindings: Bindings) -> Input {
+        // Connected controllers are opened from the device added events SDL sends at startup.
+        let subsystem = match sdl_context.game_controller() {
+            Ok(subsystem) => Some(subsystem),
+            Err(e) => {
+                println!("Game controllers disabled: {}", e);
+                None
+            }
+        };
+
+        Input {
+            bindings,
+
+            subsystem,
+            controllers: Vec::new(),
+
+            stick_x: None,
+            stick_y: None,
+
+            held: Vec::new(),
+        }
+    }
+
+    // Feeds keyboard and controller events into the joypad.
+    pub fn handle_event(&mut self, event: &Event, cpu: &mut Cpu) {
+        match *event {
+            Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                if let Some(button) = self.bindings.key(keycode) {
+                    self.press(cpu, Source::Key(keycode), button);
+                }
+            }
+            Event::KeyUp { keycode: Some(keycode), .. } => {
+                if let Some(button) = self.bindings.key(keycode) {
+                    self.release(cpu, Source::Key(keycode), button);
+                }
+            }
+
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(pressed) = self.bindings.button(button) {
+                    self.press(cpu, Source::Pad(which, button), pressed);
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(released) = self.bindings.button(button) {
+                    self.release(cpu, Source::Pad(which, button), released);
+                }
+            }
+
+            Event::ControllerAxisMotion { axis: Axis::LeftX, value, .. } => {
+                let direction = self.direction(value, Button::Left, Button::Right);
+                let previous = ::std::mem::replace(&mut self.stick_x, direction);
+                self.update_direction(cpu, previous, direction);
+            }
+            Event::ControllerAxisMotion { axis: Axis::LeftY, value, .. } => {
+                let direction = self.direction(value, Button::Up, Button::Down);
+                let previous = ::std::mem::replace(&mut self.stick_y, direction);
+                self.update_direction(cpu, previous, direction);
+            }
+
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(ref subsystem) = self.subsystem {
+                    match subsystem.open(which) {
+                        Ok(controller) => {
+                            let id = controller.instance_id();
+                            if self.controllers.iter().all(|c| c.instance_id() != id) {
+                                println!("Controller connected: {}", controller.name());
+                                self.controllers.push(controller);
+                            }
+                        }
+                        Err(e) => println!("Failed to open controller {}: {}", which, e),
+                    }
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let before = self.controllers.len();
+                self.controllers.retain(|c| c.instance_id() != which);
+
+                if self.controllers.len() != before {
+                    println!("Controller disconnected");
+                }
+
+                // Buttons held on the unplugged controller never get their up events.
+                let released: Vec<(Source, Button)> = self
+                    .held
+                    .iter()
+                    .cloned()
+                    .filter(|&(source, _)| match source {
+                        Source::Pad(id, _) => id == which,
+                        Source::Stick => true,
+                        Source::Key(_) => false,
+                    })
+                    .collect();
+
+                for (source, button) in released {
+                    self.release(cpu, source, button);
+                }
+
+                self.stick_x = None;
+                self.stick_y = None;
+            }
+
+            _ => {}
+        }
+    }
+
+    fn direction(&self, value: i16, negative: Button, positive: Button) -> Option<Button> {
+        if value < -self.bindings.dead_zone {
+            Some(negative)
+        } else if value > self.bindings.dead_zone {
+            Some(positive)
+        } else {
+            None
+        }
+    }
+
+    fn update_direction(&mut self, cpu: &mut Cpu, previous: Option<Button>, current: Option<Button>) {
+        if previous == current {
+            return;
+        }
+
+        if let Some(button) = previous {
+            self.release(cpu, Source::Stick, button);
+        }
+
+        if let Some(button) = current {
+            self.press(cpu, Source::Stick, button);
+        }
+    }
+
+    fn press(&mut self, cpu: &mut Cpu, source: Source, button: Button) {
+        if self.held.contains(&(source, button)) {
+            return;
+        }
+
+        if self.held.iter().all(|&(_, b)| b != button) {
+            cpu.set_button(button, true);
+        }
+
+        self.held.push((source, button));
+    }
+
+    fn release(&mut self, cpu: &mut Cpu, source: Source, button: Button) {
+        let before = self.held.len();
+        self.held.retain(|&held| held != (source, button));
+
+        if self.held.len() != before && self.held.iter().all(|&(_, b)| b != button) {
+            cpu.set_button(button, false);
+        }
+    }
+}
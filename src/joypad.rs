@@ -1,5 +1,19 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
 pub struct Joypad {
-    is_direction: bool,
+    // P14 and P15, true when the line is driven low and the group can be read.
+    select_direction: bool,
+    select_action: bool,
 
     down: bool,
     up: bool,
@@ -15,7 +29,8 @@ pub struct Joypad {
 impl Joypad {
     pub fn new() -> Joypad {
         Joypad {
-            is_direction: false, // false - selected
+            select_direction: false,
+            select_action: false,
 
             down: true, // false - pressed
             up: true,
@@ -29,13 +44,54 @@ impl Joypad {
         }
     }
 
+    // P1 register. Bits 6-7 always read 1, bits 4-5 echo the select lines and bits 0-3
+    // are the AND of every selected group, so no selection reads 0x0F.
     pub fn get_keys(&self) -> u8 {
-        if !self.is_direction {
-            return (self.down as u8) << 3 | (self.up as u8) << 2 | (self.left as u8) << 1
+        let mut keys = 0x0F;
+
+        if self.select_direction {
+            keys &= (self.down as u8) << 3 | (self.up as u8) << 2 | (self.left as u8) << 1
                 | (self.right as u8);
-        } else {
-            return (self.start as u8) << 3 | (self.select as u8) << 2 | (self.b as u8) << 1
+        }
+
+        if self.select_action {
+            keys &= (self.start as u8) << 3 | (self.select as u8) << 2 | (self.b as u8) << 1
                 | (self.a as u8);
         }
+
+        0xC0 | (!self.select_action as u8) << 5 | (!self.select_direction as u8) << 4 | keys
+    }
+
+    // Returns true when an input line went from high to low, which requests the joypad interrupt.
+    pub fn set_select(&mut self, value: u8) -> bool {
+        let before = self.get_keys();
+
+        self.select_direction = (value >> 4) & 0b1 == 0;
+        self.select_action = (value >> 5) & 0b1 == 0;
+
+        falling_edge(before, self.get_keys())
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.get_keys();
+
+        let line = !pressed;
+
+        match button {
+            Button::Right => self.right = line,
+            Button::Left => self.left = line,
+            Button::Up => self.up = line,
+            Button::Down => self.down = line,
+            Button::A => self.a = line,
+            Button::B => self.b = line,
+            Button::Select => self.select = line,
+            Button::Start => self.start = line,
+        }
+
+        falling_edge(before, self.get_keys())
     }
 }
+
+fn falling_edge(before: u8, after: u8) -> bool {
+    before & !after & 0x0F != 0
+}
@@ -1,36 +1,53 @@
 extern crate clap;
 extern crate sdl2;
+extern crate zip;
+extern crate flate2;
 
 #[macro_use]
 extern crate nom;
 
-pub type StrResult<T> = Result<T, &'static str>;
-
 use clap::{App, Arg};
 
 use std::process;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use sdl2::rect::Rect;
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
 mod bus;
 mod cpu;
 
+mod audio;
 mod register;
 mod clock;
 mod sound;
 mod gui;
 mod joypad;
+mod input;
+mod headless;
 mod serial;
 mod debugger;
 mod mbc;
+mod error;
 
 use bus::Bus;
-use cpu::Cpu;
+use cpu::{Cpu, CLOCK_SPEED, FRAME_CYCLES};
 
 use debugger::Debugger;
 
+use audio::Audio;
+
+use input::{Bindings, Input};
+
+use headless::{Condition, Headless, parse_hex, parse_memory_condition, EXIT_ERROR};
+
+use gui::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+use mbc::LoadOptions;
+
 fn main() {
     let matches = App::new("Gameboy Emulator")
         .version("0.1")
@@ -53,13 +70,137 @@ fn main() {
                 .short("l")
                 .help("Write cargo run <file> -- -l for enable log mode"),
         )
+        .arg(
+            Arg::with_name("accurate")
+                .short("a")
+                .long("accurate")
+                .help("Block VRAM and OAM access during the PPU modes that lock them"),
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .help("Warn about a bad header checksum instead of refusing the ROM"),
+        )
+        .arg(
+            Arg::with_name("patch")
+                .short("p")
+                .long("patch")
+                .takes_value(true)
+                .help("Apply an IPS, UPS or BPS patch when loading the ROM"),
+        )
+        .arg(
+            Arg::with_name("vsync")
+                .long("vsync")
+                .help("Synchronize presentation with the display refresh"),
+        )
+        .arg(
+            Arg::with_name("slowmo")
+                .long("slowmo")
+                .takes_value(true)
+                .default_value("2")
+                .help("Slow motion ratio, toggled with M"),
+        )
+        .arg(
+            Arg::with_name("bindings")
+                .short("b")
+                .long("bindings")
+                .takes_value(true)
+                .help("Load keyboard and controller bindings from a file"),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Run without a window or audio until --frames or an --until-* condition"),
+        )
+        .arg(
+            Arg::with_name("frames")
+                .long("frames")
+                .takes_value(true)
+                .help("Headless: stop after this many frames"),
+        )
+        .arg(
+            Arg::with_name("until-pc")
+                .long("until-pc")
+                .takes_value(true)
+                .help("Headless: stop when PC reaches this hex address"),
+        )
+        .arg(
+            Arg::with_name("until-serial")
+                .long("until-serial")
+                .takes_value(true)
+                .help("Headless: stop once the serial port has sent this text"),
+        )
+        .arg(
+            Arg::with_name("until-memory")
+                .long("until-memory")
+                .takes_value(true)
+                .help("Headless: stop when memory matches <addr>=<value>, both hex"),
+        )
+        .arg(
+            Arg::with_name("screenshot")
+                .long("screenshot")
+                .takes_value(true)
+                .help("Headless: write the last frame as a PPM image"),
+        )
+        .arg(
+            Arg::with_name("dump-ram")
+                .long("dump-ram")
+                .takes_value(true)
+                .help("Headless: write work RAM (0xC000-0xDFFF) to a file"),
+        )
+        .arg(
+            Arg::with_name("save-ram")
+                .long("save-ram")
+                .help("Headless: load and write <rom>.sav like a normal run"),
+        )
         .get_matches();
 
     let rom_file = matches.value_of("file").unwrap();
 
-    let mbc = ::mbc::get_mbc(&rom_file).unwrap();
+    let slow_motion_ratio = match matches.value_of("slowmo").unwrap().parse::<f64>() {
+        Ok(ratio) if ratio >= 1.0 => ratio,
+        _ => {
+            println!("Slow motion ratio must be a number >= 1");
+            process::exit(1);
+        }
+    };
+
+    let mut options = LoadOptions::new();
+    options.lenient_checksum = matches.is_present("lenient");
+    options.patch = matches.value_of("patch").map(PathBuf::from);
+
+    // Headless runs start from blank save RAM and leave <rom>.sav alone so they are reproducible.
+    let mut use_save = !matches.is_present("headless") || matches.is_present("save-ram");
+    options.load_save = use_save;
 
-    let bus = Bus::new(mbc);
+    let mut cartridge = ::mbc::get_mbc(&rom_file, &options);
+
+    // A save that exists but can't be read must not be overwritten by blank RAM.
+    if let Err(ref e @ ::error::Error::Save { .. }) = cartridge {
+        println!("{}, saving is disabled", e);
+        use_save = false;
+        options.load_save = false;
+        cartridge = ::mbc::get_mbc(&rom_file, &options);
+    }
+
+    let (header, mbc) = match cartridge {
+        Ok(cartridge) => cartridge,
+        Err(e) => {
+            println!("Failed to load {}: {}", rom_file, e);
+            process::exit(1);
+        }
+    };
+
+    let title = format!("{} - Gameboy Emulator by Vitaly Shvetsov", header.title);
+
+    let mut bus = Bus::new(header, mbc);
+    if use_save {
+        bus.set_save_path(::mbc::save_path(rom_file));
+    }
+
+    if matches.is_present("accurate") {
+        bus.enable_access_blocking();
+    }
 
     let mut cpu = Cpu::new(bus);
 
@@ -73,64 +214,113 @@ fn main() {
         cpu.enable_log();
         let mut debugger = Debugger::new(cpu);
         debugger.run();
+    } else if matches.is_present("headless") {
+        let mut headless = Headless::new();
+
+        if let Some(frames) = matches.value_of("frames") {
+            headless.frames = Some(frames.parse().unwrap_or_else(|_| {
+                println!("Invalid frame count {}", frames);
+                process::exit(EXIT_ERROR);
+            }));
+        }
+
+        if let Some(pc) = matches.value_of("until-pc") {
+            let pc = parse_hex(pc).unwrap_or_else(|| {
+                println!("Invalid address {}", pc);
+                process::exit(EXIT_ERROR);
+            });
+            headless.conditions.push(Condition::Pc(pc));
+        }
+
+        if let Some(text) = matches.value_of("until-serial") {
+            headless.conditions.push(Condition::Serial(text.to_string()));
+        }
+
+        if let Some(memory) = matches.value_of("until-memory") {
+            let condition = parse_memory_condition(memory).unwrap_or_else(|| {
+                println!("Invalid memory condition {}, expected <addr>=<value> in hex", memory);
+                process::exit(EXIT_ERROR);
+            });
+            headless.conditions.push(condition);
+        }
+
+        if headless.frames.is_none() && headless.conditions.is_empty() {
+            println!("--headless needs --frames or an --until-* condition");
+            process::exit(EXIT_ERROR);
+        }
+
+        headless.screenshot = matches.value_of("screenshot").map(String::from);
+        headless.ram_dump = matches.value_of("dump-ram").map(String::from);
+
+        process::exit(headless.run(&mut cpu));
     } else {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
         let window = video_subsystem
-            .window("Gameboy Emulator by Vitaly Shvetsov", 160 * 5, 144 * 5)
+            .window(&title, SCREEN_WIDTH as u32 * 5, SCREEN_HEIGHT as u32 * 5)
             .position_centered()
             .opengl()
             .build()
             .unwrap();
 
-        let mut renderer = window
+        let mut canvas = window
             .into_canvas()
-            .index(find_sdl_gl_driver().unwrap())
-            .build()
-            .unwrap();
+            .index(find_sdl_gl_driver().unwrap());
 
-        let mut rect = Rect::new(5, 5, 5, 5);
+        if matches.is_present("vsync") {
+            canvas = canvas.present_vsync();
+        }
 
-        let black = sdl2::pixels::Color::RGB(0, 0, 0);
-        let white = sdl2::pixels::Color::RGB(255, 255, 255);
+        let mut renderer = canvas.build().unwrap();
 
-        let mut events = sdl_context.event_pump().unwrap();
+        let texture_creator = renderer.texture_creator();
 
-        cpu.test_draw();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+            .unwrap();
 
-        loop {
-            cpu.update_ime();
+        let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+
+        let mut audio = match Audio::new(&sdl_context) {
+            Ok(audio) => {
+                cpu.enable_sound_output();
+                Some(audio)
+            }
+            Err(e) => {
+                println!("Audio disabled: {}", e);
+                None
+            }
+        };
+
+        let mut samples = Vec::new();
 
-            cpu.run_next_instruction(false);
+        let bindings = match matches.value_of("bindings") {
+            Some(path) => match Bindings::load(path) {
+                Ok(bindings) => bindings,
+                Err(e) => {
+                    println!("Failed to load bindings {}", e);
+                    process::exit(1);
+                }
+            },
+            None => Bindings::new(),
+        };
 
-            let _ = renderer.set_draw_color(white);
-            let _ = renderer.clear();
+        let mut input = Input::new(&sdl_context, bindings);
 
-            for x in 0..160 {
-                for y in 0..144 {
-                    let data = &cpu.get_data_gui(x, y);
+        let mut events = sdl_context.event_pump().unwrap();
 
-                    let c1 = (data >> 16) & 0xFF as u32;
-                    let c2 = (data >> 8) & 0xFF as u32;
-                    let c3 = data & 0xFF as u32;
+        let frame_duration = Duration::from_nanos(FRAME_CYCLES * 1_000_000_000 / CLOCK_SPEED);
+        let mut deadline = Instant::now();
 
-                    if c1 != 0x00  as u32 || c2 != 0x00  as u32 || c3 != 0x00  as u32 {
-                        let new_color = sdl2::pixels::Color::RGB(c1 as u8, c2 as u8, c3 as u8);
+        let mut paused = false;
+        let mut advance = false;
+        let mut fast_forward = false;
+        let mut slow_motion = false;
 
-                        let x_pos = (x * 5) as i32;
-                        let y_pos = (y * 5) as i32;
-                        
-                        rect.set_y(y_pos);
-                        rect.set_x(x_pos);
-                        
-                        let _ = renderer.fill_rect(rect);
-                        let _ = renderer.set_draw_color(new_color);
-                    }
-                }
-            }
-            let _ = renderer.present();
+        let mut frames: u64 = 0;
 
+        loop {
             for event in events.poll_iter() {
                 match event {
                     Event::Quit { .. }
@@ -138,11 +328,80 @@ fn main() {
                         keycode: Some(Keycode::Escape),
                         ..
                     } => {
+                        cpu.save();
                         process::exit(1);
                     }
 
-                    _ => {}
+                    Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                        paused = !paused;
+                        println!("{}", if paused { "Paused" } else { "Resumed" });
+                    }
+
+                    Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                        if paused {
+                            advance = true;
+                        }
+                    }
+
+                    Event::KeyDown { keycode: Some(Keycode::Tab), .. } => fast_forward = true,
+                    Event::KeyUp { keycode: Some(Keycode::Tab), .. } => fast_forward = false,
+
+                    Event::KeyDown { keycode: Some(Keycode::M), repeat: false, .. } => {
+                        slow_motion = !slow_motion;
+                        println!("Slow motion {}", if slow_motion { "on" } else { "off" });
+                    }
+
+                    event => input.handle_event(&event, &mut cpu),
+                }
+            }
+
+            let emulated = !paused || advance;
+
+            if emulated {
+                advance = false;
+
+                cpu.run_frame();
+                frames += 1;
+
+                if let Some(motor) = cpu.rumble_event() {
+                    println!("Rumble {}", if motor { "on" } else { "off" });
+                }
+
+                if let Some(ref mut audio) = audio {
+                    cpu.drain_samples(&mut samples);
+                    audio.push(&samples);
+                    samples.clear();
                 }
+
+                if cpu.take_frame() {
+                    // Upload the finished frame once and let the renderer scale it to the window.
+                    cpu.copy_frame(&mut frame);
+                    let _ = texture.update(None, &frame, SCREEN_WIDTH * 3);
+                }
+            }
+
+            // Fast-forward only shows every 8th frame so presentation doesn't throttle it.
+            if !(fast_forward && emulated) || frames % 8 == 0 {
+                renderer.clear();
+                let _ = renderer.copy(&texture, None, None);
+                renderer.present();
+            }
+
+            // While paused there is nothing to fast-forward, keep pacing so the loop doesn't spin.
+            if fast_forward && emulated {
+                deadline = Instant::now();
+                continue;
+            }
+
+            let ratio = if slow_motion { slow_motion_ratio } else { 1.0 };
+            deadline += frame_duration.mul_f64(ratio);
+
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            } else if now - deadline > frame_duration * 4 {
+                // Too far behind, e.g. after the window was dragged. Don't try to catch up.
+                deadline = now;
             }
         }
     }
@@ -0,0 +1,368 @@
+use error::Error;
+
+// Cartridge header, 0x0100-0x014F of every ROM.
+
+// 0x0104-0x0133 - Nintendo logo
+// 0x0134-0x0143 - title
+// 0x013F-0x0142 - manufacturer code (newer cartridges)
+// 0x0143 - CGB flag
+// 0x0144-0x0145 - new licensee code
+// 0x0146 - SGB flag
+// 0x0147 - type
+// 0x0148 - ROM size
+// 0x0149 - RAM size
+// 0x014A - destination code
+// 0x014B - old licensee code
+// 0x014C - version
+// 0x014D - header checksum
+// 0x014E-0x014F - global checksum
+
+pub const HEADER_END: usize = 0x0150;
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CartridgeType {
+    RomOnly,
+    Mbc1,
+    Mbc1Ram,
+    Mbc1RamBattery,
+    Mbc2,
+    Mbc2Battery,
+    RomRam,
+    RomRamBattery,
+    Mmm01,
+    Mmm01Ram,
+    Mmm01RamBattery,
+    Mbc3TimerBattery,
+    Mbc3TimerRamBattery,
+    Mbc3,
+    Mbc3Ram,
+    Mbc3RamBattery,
+    Mbc5,
+    Mbc5Ram,
+    Mbc5RamBattery,
+    Mbc5Rumble,
+    Mbc5RumbleRam,
+    Mbc5RumbleRamBattery,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1RamBattery,
+    Unknown(u8),
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> CartridgeType {
+        match code {
+            0x00 => CartridgeType::RomOnly,
+            0x01 => CartridgeType::Mbc1,
+            0x02 => CartridgeType::Mbc1Ram,
+            0x03 => CartridgeType::Mbc1RamBattery,
+            0x05 => CartridgeType::Mbc2,
+            0x06 => CartridgeType::Mbc2Battery,
+            0x08 => CartridgeType::RomRam,
+            0x09 => CartridgeType::RomRamBattery,
+            0x0B => CartridgeType::Mmm01,
+            0x0C => CartridgeType::Mmm01Ram,
+            0x0D => CartridgeType::Mmm01RamBattery,
+            0x0F => CartridgeType::Mbc3TimerBattery,
+            0x10 => CartridgeType::Mbc3TimerRamBattery,
+            0x11 => CartridgeType::Mbc3,
+            0x12 => CartridgeType::Mbc3Ram,
+            0x13 => CartridgeType::Mbc3RamBattery,
+            0x19 => CartridgeType::Mbc5,
+            0x1A => CartridgeType::Mbc5Ram,
+            0x1B => CartridgeType::Mbc5RamBattery,
+            0x1C => CartridgeType::Mbc5Rumble,
+            0x1D => CartridgeType::Mbc5RumbleRam,
+            0x1E => CartridgeType::Mbc5RumbleRamBattery,
+            0xFC => CartridgeType::PocketCamera,
+            0xFD => CartridgeType::Tama5,
+            0xFE => CartridgeType::HuC3,
+            0xFF => CartridgeType::HuC1RamBattery,
+            n => CartridgeType::Unknown(n),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match *self {
+            CartridgeType::RomOnly => 0x00,
+            CartridgeType::Mbc1 => 0x01,
+            CartridgeType::Mbc1Ram => 0x02,
+            CartridgeType::Mbc1RamBattery => 0x03,
+            CartridgeType::Mbc2 => 0x05,
+            CartridgeType::Mbc2Battery => 0x06,
+            CartridgeType::RomRam => 0x08,
+            CartridgeType::RomRamBattery => 0x09,
+            CartridgeType::Mmm01 => 0x0B,
+            CartridgeType::Mmm01Ram => 0x0C,
+            CartridgeType::Mmm01RamBattery => 0x0D,
+            CartridgeType::Mbc3TimerBattery => 0x0F,
+            CartridgeType::Mbc3TimerRamBattery => 0x10,
+            CartridgeType::Mbc3 => 0x11,
+            CartridgeType::Mbc3Ram => 0x12,
+            CartridgeType::Mbc3RamBattery => 0x13,
+            CartridgeType::Mbc5 => 0x19,
+            CartridgeType::Mbc5Ram => 0x1A,
+            CartridgeType::Mbc5RamBattery => 0x1B,
+            CartridgeType::Mbc5Rumble => 0x1C,
+            CartridgeType::Mbc5RumbleRam => 0x1D,
+            CartridgeType::Mbc5RumbleRamBattery => 0x1E,
+            CartridgeType::PocketCamera => 0xFC,
+            CartridgeType::Tama5 => 0xFD,
+            CartridgeType::HuC3 => 0xFE,
+            CartridgeType::HuC1RamBattery => 0xFF,
+            CartridgeType::Unknown(n) => n,
+        }
+    }
+
+    // External RAM on the cartridge. MBC2 RAM is built into the mapper and not counted here.
+    pub fn has_ram(&self) -> bool {
+        match *self {
+            CartridgeType::Mbc1Ram
+            | CartridgeType::Mbc1RamBattery
+            | CartridgeType::RomRam
+            | CartridgeType::RomRamBattery
+            | CartridgeType::Mmm01Ram
+            | CartridgeType::Mmm01RamBattery
+            | CartridgeType::Mbc3TimerRamBattery
+            | CartridgeType::Mbc3Ram
+            | CartridgeType::Mbc3RamBattery
+            | CartridgeType::Mbc5Ram
+            | CartridgeType::Mbc5RamBattery
+            | CartridgeType::Mbc5RumbleRam
+            | CartridgeType::Mbc5RumbleRamBattery
+            | CartridgeType::PocketCamera
+            | CartridgeType::HuC3
+            | CartridgeType::HuC1RamBattery => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        match *self {
+            CartridgeType::Mbc1RamBattery
+            | CartridgeType::Mbc2Battery
+            | CartridgeType::RomRamBattery
+            | CartridgeType::Mmm01RamBattery
+            | CartridgeType::Mbc3TimerBattery
+            | CartridgeType::Mbc3TimerRamBattery
+            | CartridgeType::Mbc3RamBattery
+            | CartridgeType::Mbc5RamBattery
+            | CartridgeType::Mbc5RumbleRamBattery
+            | CartridgeType::PocketCamera
+            | CartridgeType::HuC3
+            | CartridgeType::HuC1RamBattery => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_timer(&self) -> bool {
+        match *self {
+            CartridgeType::Mbc3TimerBattery | CartridgeType::Mbc3TimerRamBattery => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        match *self {
+            CartridgeType::Mbc5Rumble
+            | CartridgeType::Mbc5RumbleRam
+            | CartridgeType::Mbc5RumbleRamBattery => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+
+    pub cgb_flag: u8,
+    pub sgb_flag: bool,
+
+    pub new_licensee: String,
+    pub old_licensee: u8,
+
+    pub cartridge_type: CartridgeType,
+    pub rom_size: u8,
+    pub ram_size: u8,
+
+    pub version: u8,
+
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+
+    pub logo_valid: bool,
+}
+
+impl CartridgeHeader {
+    pub fn parse(data: &[u8]) -> ::error::Result<CartridgeHeader> {
+        if data.len() < HEADER_END {
+            return Err(Error::TruncatedRom { size: data.len() });
+        }
+
+        if ::mbc::rom_size(data[0x0148]).is_none() {
+            return Err(Error::UnknownRomSize(data[0x0148]));
+        }
+
+        let cgb_flag = data[0x0143];
+
+        // CGB era cartridges shortened the title to make room for a manufacturer code.
+        let code = &data[0x013F..0x0143];
+        let manufacturer_code = if cgb_flag & 0x80 != 0 && code.iter().all(|c| c.is_ascii_uppercase()) {
+            Some(ascii(code))
+        } else {
+            None
+        };
+
+        let title = match (manufacturer_code.is_some(), cgb_flag & 0x80 != 0) {
+            (true, _) => ascii(&data[0x0134..0x013F]),
+            (false, true) => ascii(&data[0x0134..0x0143]),
+            (false, false) => ascii(&data[0x0134..0x0144]),
+        };
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer_code,
+
+            cgb_flag,
+            sgb_flag: data[0x0146] == 0x03,
+
+            new_licensee: ascii(&data[0x0144..0x0146]),
+            old_licensee: data[0x014B],
+
+            cartridge_type: CartridgeType::from_code(data[0x0147]),
+            rom_size: data[0x0148],
+            ram_size: data[0x0149],
+
+            version: data[0x014C],
+
+            header_checksum: data[0x014D],
+            global_checksum: (data[0x014E] as u16) << 8 | data[0x014F] as u16,
+
+            logo_valid: data[0x0104..0x0134] == NINTENDO_LOGO[..],
+        })
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    pub fn cgb_only(&self) -> bool {
+        self.cgb_flag == 0xC0
+    }
+
+    pub fn ram_bytes(&self) -> usize {
+        if self.cartridge_type.has_ram() { ::mbc::ram_size(self.ram_size) } else { 0 }
+    }
+
+    pub fn rom_bytes(&self) -> usize {
+        ::mbc::rom_size(self.rom_size).unwrap_or(0)
+    }
+
+    pub fn rom_banks(&self) -> u32 {
+        ::mbc::bank_count(self.rom_size)
+    }
+}
+
+// Header checksum over 0x0134-0x014C, verified by the boot ROM.
+pub fn header_checksum(data: &[u8]) -> u8 {
+    let mut value: u8 = 0;
+    for i in 0x134 .. 0x14D {
+        value = value.wrapping_sub(data[i]).wrapping_sub(1);
+    }
+    value
+}
+
+// Global checksum, the 16-bit sum of every ROM byte except the checksum itself. Not checked by hardware.
+pub fn global_checksum(data: &[u8]) -> u16 {
+    data.iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 0x014E && i != 0x014F)
+        .fold(0u16, |sum, (_, &value)| sum.wrapping_add(value as u16))
+}
+
+fn ascii(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| if c >= 0x20 && c < 0x7F { c as char } else { ' ' })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(title: &[u8], cgb_flag: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0143] = cgb_flag;
+        rom
+    }
+
+    #[test]
+    fn dmg_title_is_sixteen_characters() {
+        // Without bit 7 set the CGB flag byte is the last title character.
+        let parsed = CartridgeHeader::parse(&header(b"SIXTEEN CHARS A", b'B')).unwrap();
+
+        assert_eq!(parsed.title, "SIXTEEN CHARS AB");
+        assert_eq!(parsed.manufacturer_code, None);
+    }
+
+    #[test]
+    fn cgb_title_with_manufacturer_code() {
+        let parsed = CartridgeHeader::parse(&header(b"POKEMON CRYAPXE", 0x80)).unwrap();
+
+        assert_eq!(parsed.title, "POKEMON CRY");
+        assert_eq!(parsed.manufacturer_code, Some("APXE".to_string()));
+        assert!(parsed.supports_cgb());
+        assert!(!parsed.cgb_only());
+    }
+
+    #[test]
+    fn cgb_title_without_manufacturer_code() {
+        // Lowercase or padding in 0x013F-0x0142 means the title runs on.
+        let parsed = CartridgeHeader::parse(&header(b"LONG CGB TITLE", 0xC0)).unwrap();
+
+        assert_eq!(parsed.title, "LONG CGB TITLE");
+        assert_eq!(parsed.manufacturer_code, None);
+        assert!(parsed.cgb_only());
+
+        let parsed = CartridgeHeader::parse(&header(b"MIXED CASEabcd", 0x80)).unwrap();
+        assert_eq!(parsed.title, "MIXED CASEabcd");
+        assert_eq!(parsed.manufacturer_code, None);
+    }
+
+    #[test]
+    fn uppercase_code_on_dmg_cartridge_stays_in_the_title() {
+        let parsed = CartridgeHeader::parse(&header(b"DMG GAMETITLEAB", 0x00)).unwrap();
+
+        assert_eq!(parsed.title, "DMG GAMETITLEAB");
+        assert_eq!(parsed.manufacturer_code, None);
+    }
+
+    #[test]
+    fn rejects_short_and_unknown_size() {
+        match CartridgeHeader::parse(&[0; 0x014F]) {
+            Err(Error::TruncatedRom { size: 0x014F }) => {}
+            _ => panic!("expected a truncated ROM error"),
+        }
+
+        let mut rom = header(b"TEST", 0x00);
+        rom[0x0148] = 0x20;
+        match CartridgeHeader::parse(&rom) {
+            Err(Error::UnknownRomSize(0x20)) => {}
+            _ => panic!("expected an unknown ROM size error"),
+        }
+    }
+}
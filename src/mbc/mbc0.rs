@@ -5,14 +5,14 @@ pub struct MBC0 {
 }
 
 impl MBC0 {
-    pub fn new(data: Vec<u8>) -> ::StrResult<MBC0> {
+    pub fn new(data: Vec<u8>) -> ::error::Result<MBC0> {
         Ok(MBC0 { rom: data })
     }
 }
 
 impl MBC for MBC0 {
     fn readrom(&self, address: u16) -> u8 { 
-        self.rom[address as usize] 
+        *self.rom.get(address as usize).unwrap_or(&0xFF)
     }
 
     fn writerom(&mut self, _address: u16, _value: u8) { }
@@ -1,21 +1,23 @@
-use mbc::MBC;
+use mbc::{CartridgeHeader, MBC};
 
 pub struct MBC1 {
     rom: Vec<u8>,
     ram: Vec<u8>,
     ram_on: bool,
     ram_mode: bool,
-    rom_bank: usize,
-    ram_bank: usize,
+    rom_bank: usize, // BANK1, 5 bits.
+    ram_bank: usize, // BANK2, 2 bits. Upper ROM bank bits or RAM bank.
+    multicart: bool,
+    battery: bool,
+    ram_dirty: bool,
 }
 
 impl MBC1 {
-    pub fn new(data: Vec<u8>) -> ::StrResult<MBC1> {
-        let size = match data[0x0147] {
-            0x01 => 0,
-            0x02 => ::mbc::ram_size(data[0x0149]),
-            _ => 0,
-        };
+    pub fn new(data: Vec<u8>, header: &CartridgeHeader) -> ::error::Result<MBC1> {
+        let size = header.ram_bytes();
+
+        let multicart = is_multicart(&data);
+        let battery = header.cartridge_type.has_battery();
 
         Ok(MBC1 { 
             rom: data,
@@ -24,51 +26,198 @@ impl MBC1 {
             ram_mode: false,
             rom_bank: 1,
             ram_bank: 0,
+            multicart,
+            battery,
+            ram_dirty: false,
         })
     }
+
+    // MBC1M boards wire BANK2 to ROM address bits 18-19 instead of 19-20, skipping BANK1 bit 4.
+    fn upper_shift(&self) -> usize {
+        if self.multicart { 4 } else { 5 }
+    }
+
+    fn lower_bank(&self) -> usize {
+        if self.multicart { self.rom_bank & 0x0F } else { self.rom_bank }
+    }
+
+    fn read_bank(&self, bank: usize, address: u16) -> u8 {
+        let banks = ::std::cmp::max(self.rom.len() / 0x4000, 1);
+        let bank = bank & (banks.next_power_of_two() - 1);
+
+        let addr = bank * 0x4000 | ((address as usize) & 0x3FFF);
+        *self.rom.get(addr).unwrap_or(&0xFF)
+    }
+
+    fn ram_address(&self, address: u16) -> usize {
+        let bank = if self.ram_mode { self.ram_bank } else { 0 };
+
+        ((bank * 0x2000) | ((address & 0x1FFF) as usize)) % self.ram.len()
+    }
 }
 
 impl MBC for MBC1 {
     fn readrom(&self, address: u16) -> u8 { 
         if address < 0x4000 {
-            self.rom[address as usize] 
+            // Mode 1 also applies BANK2 to the fixed region.
+            let bank = if self.ram_mode { self.ram_bank << self.upper_shift() } else { 0 };
+            self.read_bank(bank, address)
         } else {
-            let addr = self.rom_bank * 0x4000 | ((address as usize) & 0x3FFF);
-            self.rom[addr as usize]
+            let bank = self.ram_bank << self.upper_shift() | self.lower_bank();
+            self.read_bank(bank, address)
         }
     }
 
     fn writerom(&mut self, address: u16, value: u8) { 
         match address {
             0x0000 ... 0x1FFF => {
-                self.ram_on = (value & 0xFF) == 0x0A;
+                self.ram_on = (value & 0x0F) == 0x0A;
+            }
+            0x2000 ... 0x3FFF => {
+                // The zero check sees all five bits, so 0x20 still maps to bank 1.
+                self.rom_bank = match (value & 0x1F) as usize {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000 ... 0x5FFF => {
+                self.ram_bank = (value & 0x03) as usize;
             }
             0x6000 ... 0x7FFF => { 
-                self.ram_mode = (value & 0xFF) == 0x01;
+                self.ram_mode = (value & 0x01) == 0x01;
             },
-            _ => panic!("Unexpected write for MBC1"),
+            _ => {}
         }
     }
     
     fn readram(&self, address: u16) -> u8 { 
-        if !self.ram_on {
-            return 0;
-        }
-        if self.ram_mode { 
-            self.ram[(self.ram_bank * 0x2000) | ((address & 0x1FFF) as usize)]
-        } else { 
-            self.ram[(address & 0x1FFF) as usize]
+        if !self.ram_on || self.ram.is_empty() {
+            return 0xFF;
         }
+        self.ram[self.ram_address(address)]
      }
 
     fn writeram(&mut self, address: u16, value: u8) { 
-        if !self.ram_on {
+        if !self.ram_on || self.ram.is_empty() {
             return;
         }
-        if self.ram_mode { 
-            self.ram[(self.ram_bank * 0x2000) | ((address & 0x1FFF) as usize)] = value;
-        } else { 
-            self.ram[(address & 0x1FFF) as usize] = value;
+        let addr = self.ram_address(address);
+        self.ram[addr] = value;
+        self.ram_dirty = true;
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn dump_ram(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        let len = ::std::cmp::min(data.len(), self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        ::std::mem::replace(&mut self.ram_dirty, false)
+    }
+}
+
+// MBC1M multicarts are 1 MiB and repeat the Nintendo logo at the start of every 256 KiB game.
+fn is_multicart(data: &[u8]) -> bool {
+    if data.len() != 1024 * 1024 {
+        return false;
+    }
+
+    data[0x40104..0x40134] == data[0x0104..0x0134]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ROM whose every bank starts with its own bank number.
+    fn rom(size_code: u8, banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * 0x4000];
+        for bank in 0..banks {
+            rom[bank * 0x4000] = bank as u8;
+        }
+
+        rom[0x0104..0x0134].copy_from_slice(&[0xCE; 48]);
+        rom[0x0147] = 0x01;
+        rom[0x0148] = size_code;
+        rom
+    }
+
+    fn mbc1(rom: Vec<u8>) -> MBC1 {
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        MBC1::new(rom, &header).unwrap()
+    }
+
+    #[test]
+    fn bank_zero_maps_to_the_next_bank() {
+        let mut mbc = mbc1(rom(0x06, 128));
+
+        for &(bank2, expected) in [(0, 0x01), (1, 0x21), (2, 0x41), (3, 0x61)].iter() {
+            mbc.writerom(0x4000, bank2);
+            mbc.writerom(0x2000, 0x00);
+            assert_eq!(mbc.readrom(0x4000), expected);
+
+            // Only the low five bits are written, 0x20 is bank zero as well.
+            mbc.writerom(0x2000, 0x20);
+            assert_eq!(mbc.readrom(0x4000), expected);
         }
+
+        mbc.writerom(0x4000, 0);
+        mbc.writerom(0x2000, 0x1F);
+        assert_eq!(mbc.readrom(0x4000), 0x1F);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn mode_1_banks_the_fixed_region() {
+        let mut mbc = mbc1(rom(0x06, 128));
+        mbc.writerom(0x4000, 2);
+
+        assert_eq!(mbc.readrom(0x0000), 0x00);
+
+        mbc.writerom(0x6000, 1);
+        assert_eq!(mbc.readrom(0x0000), 0x40);
+        assert_eq!(mbc.readrom(0x4000), 0x41);
+
+        mbc.writerom(0x6000, 0);
+        assert_eq!(mbc.readrom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn multicart_shifts_bank2_by_four() {
+        let mut rom = rom(0x05, 64);
+        let logo = rom[0x0104..0x0134].to_vec();
+        rom[0x40104..0x40134].copy_from_slice(&logo);
+
+        let mut mbc = mbc1(rom);
+        assert!(mbc.multicart);
+
+        // BANK1 bit 4 is not connected.
+        mbc.writerom(0x4000, 1);
+        mbc.writerom(0x2000, 0x12);
+        assert_eq!(mbc.readrom(0x4000), 0x12);
+
+        mbc.writerom(0x4000, 3);
+        mbc.writerom(0x2000, 0x05);
+        assert_eq!(mbc.readrom(0x4000), 0x35);
+
+        mbc.writerom(0x6000, 1);
+        assert_eq!(mbc.readrom(0x0000), 0x30);
+    }
+
+    #[test]
+    fn plain_1_mib_cartridge_is_not_a_multicart() {
+        let mut mbc = mbc1(rom(0x05, 64));
+        assert!(!mbc.multicart);
+
+        mbc.writerom(0x4000, 1);
+        mbc.writerom(0x2000, 0x12);
+        assert_eq!(mbc.readrom(0x4000), 0x32);
+    }
+}
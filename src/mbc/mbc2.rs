@@ -0,0 +1,84 @@
+use mbc::{CartridgeHeader, MBC};
+
+pub struct MBC2 {
+    rom: Vec<u8>,
+    ram: [u8; 512], // Built-in 512x4 bit RAM, only the lower nibble is stored.
+    ram_on: bool,
+    rom_bank: usize,
+    battery: bool,
+    ram_dirty: bool,
+}
+
+impl MBC2 {
+    pub fn new(data: Vec<u8>, header: &CartridgeHeader) -> ::error::Result<MBC2> {
+        let battery = header.cartridge_type.has_battery();
+
+        Ok(MBC2 {
+            rom: data,
+            ram: [0; 512],
+            ram_on: false,
+            rom_bank: 1,
+            battery,
+            ram_dirty: false,
+        })
+    }
+}
+
+impl MBC for MBC2 {
+    fn readrom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank };
+
+        let addr = bank * 0x4000 | ((address as usize) & 0x3FFF);
+        *self.rom.get(addr % ::std::cmp::max(self.rom.len(), 1)).unwrap_or(&0xFF)
+    }
+
+    fn writerom(&mut self, address: u16, value: u8) {
+        if address >= 0x4000 {
+            return;
+        }
+
+        // Address bit 8 selects between RAM enable (0) and ROM bank (1).
+        if address & 0x0100 == 0 {
+            self.ram_on = (value & 0x0F) == 0x0A;
+        } else {
+            self.rom_bank = match (value & 0x0F) as usize {
+                0 => 1,
+                n => n,
+            };
+        }
+    }
+
+    fn readram(&self, address: u16) -> u8 {
+        if !self.ram_on {
+            return 0xFF;
+        }
+        // Only 9 address bits are decoded, the RAM echoes across 0xA000-0xBFFF.
+        0xF0 | self.ram[(address & 0x01FF) as usize]
+    }
+
+    fn writeram(&mut self, address: u16, value: u8) {
+        if !self.ram_on {
+            return;
+        }
+        self.ram[(address & 0x01FF) as usize] = value & 0x0F;
+        self.ram_dirty = true;
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn dump_ram(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        for (i, value) in data.iter().take(512).enumerate() {
+            self.ram[i] = value & 0x0F;
+        }
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        ::std::mem::replace(&mut self.ram_dirty, false)
+    }
+}
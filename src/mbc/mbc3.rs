@@ -0,0 +1,356 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mbc::{CartridgeHeader, MBC};
+
+// Save files end with the RTC state in the layout used by VBA-M and BGB:
+// five current registers, five latched registers (u32 each) and a 64-bit unix timestamp.
+const RTC_FOOTER_SIZE: usize = 48;
+
+#[derive(Clone, Copy)]
+struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16, // 9 bits.
+    halt: bool,
+    carry: bool,
+
+    // Unix time the registers above were last brought up to date.
+    timestamp: u64,
+}
+
+impl Rtc {
+    fn new() -> Rtc {
+        Rtc {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halt: false,
+            carry: false,
+
+            timestamp: now(),
+        }
+    }
+
+    // Returns the registers advanced by the wall-clock time elapsed since `timestamp`.
+    fn advanced(&self, now: u64) -> Rtc {
+        let mut rtc = *self;
+        rtc.timestamp = now;
+
+        if self.halt || now <= self.timestamp {
+            return rtc;
+        }
+
+        let elapsed = now - self.timestamp;
+
+        let seconds = rtc.seconds as u64 + elapsed;
+        rtc.seconds = (seconds % 60) as u8;
+
+        let minutes = rtc.minutes as u64 + seconds / 60;
+        rtc.minutes = (minutes % 60) as u8;
+
+        let hours = rtc.hours as u64 + minutes / 60;
+        rtc.hours = (hours % 24) as u8;
+
+        let days = rtc.days as u64 + hours / 24;
+        rtc.days = (days % 512) as u16;
+
+        if days >= 512 {
+            rtc.carry = true;
+        }
+
+        rtc
+    }
+
+    fn load(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            0x0C => {
+                (self.carry as u8) << 7 | (self.halt as u8) << 6 | ((self.days >> 8) as u8 & 0b1)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn store(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((value & 0b1) as u16) << 8;
+                self.halt = (value >> 6) & 0b1 == 1;
+                self.carry = (value >> 7) & 0b1 == 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn write_footer(&self, latched: &Rtc, buf: &mut Vec<u8>) {
+        for rtc in [self, latched].iter() {
+            for register in 0x08..0x0D {
+                buf.extend_from_slice(&(rtc.load(register) as u32).to_le_bytes());
+            }
+        }
+        buf.extend_from_slice(&self.timestamp.to_le_bytes());
+    }
+
+    fn read_footer(data: &[u8]) -> (Rtc, Rtc) {
+        let word = |i: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[i * 4..i * 4 + 4]);
+            u32::from_le_bytes(bytes) as u8
+        };
+
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&data[40..48]);
+        let timestamp = u64::from_le_bytes(timestamp);
+
+        let mut rtc = Rtc::new();
+        let mut latched = Rtc::new();
+
+        for i in 0..5 {
+            rtc.store(0x08 + i as u8, word(i));
+            latched.store(0x08 + i as u8, word(i + 5));
+        }
+
+        rtc.timestamp = timestamp;
+        latched.timestamp = timestamp;
+
+        (rtc, latched)
+    }
+}
+
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_on: bool,
+    rom_bank: usize,
+    ram_bank: u8, // 0x00-0x03 select a RAM bank, 0x08-0x0C an RTC register.
+    rtc: Option<Rtc>,
+    latched: Rtc,
+    latch: u8,
+    battery: bool,
+    ram_dirty: bool,
+}
+
+impl MBC3 {
+    pub fn new(data: Vec<u8>, header: &CartridgeHeader) -> ::error::Result<MBC3> {
+        let size = header.ram_bytes();
+
+        let ram: Vec<u8> = ::std::iter::repeat(0).take(size).collect();
+
+        let rtc = if header.cartridge_type.has_timer() { Some(Rtc::new()) } else { None };
+
+        let battery = header.cartridge_type.has_battery();
+
+        Ok(MBC3 {
+            rom: data,
+            ram,
+            ram_on: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rtc,
+            latched: Rtc::new(),
+            latch: 0xFF,
+            battery,
+            ram_dirty: false,
+        })
+    }
+
+    fn update_rtc(&mut self) {
+        if let Some(rtc) = self.rtc {
+            self.rtc = Some(rtc.advanced(now()));
+        }
+    }
+}
+
+impl MBC for MBC3 {
+    fn readrom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank };
+
+        let addr = bank * 0x4000 | ((address as usize) & 0x3FFF);
+        *self.rom.get(addr % ::std::cmp::max(self.rom.len(), 1)).unwrap_or(&0xFF)
+    }
+
+    fn writerom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ... 0x1FFF => {
+                self.ram_on = (value & 0x0F) == 0x0A;
+            }
+            0x2000 ... 0x3FFF => {
+                self.rom_bank = match (value & 0x7F) as usize {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000 ... 0x5FFF => {
+                self.ram_bank = value;
+            }
+            0x6000 ... 0x7FFF => {
+                // Writing 0x00 then 0x01 copies the running clock into the latched registers.
+                if self.latch == 0x00 && value == 0x01 {
+                    self.update_rtc();
+                    if let Some(rtc) = self.rtc {
+                        self.latched = rtc;
+                    }
+                }
+                self.latch = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn readram(&self, address: u16) -> u8 {
+        if !self.ram_on {
+            return 0xFF;
+        }
+
+        match self.ram_bank {
+            0x00 ... 0x03 => {
+                if self.ram.is_empty() {
+                    return 0xFF;
+                }
+                let addr = (self.ram_bank as usize * 0x2000) | (address & 0x1FFF) as usize;
+                self.ram[addr % self.ram.len()]
+            }
+            0x08 ... 0x0C if self.rtc.is_some() => self.latched.load(self.ram_bank),
+            _ => 0xFF,
+        }
+    }
+
+    fn writeram(&mut self, address: u16, value: u8) {
+        if !self.ram_on {
+            return;
+        }
+
+        match self.ram_bank {
+            0x00 ... 0x03 => {
+                if self.ram.is_empty() {
+                    return;
+                }
+                let addr = (self.ram_bank as usize * 0x2000) | (address & 0x1FFF) as usize;
+                let len = self.ram.len();
+                self.ram[addr % len] = value;
+                self.ram_dirty = true;
+            }
+            0x08 ... 0x0C => {
+                self.update_rtc();
+                if let Some(ref mut rtc) = self.rtc {
+                    rtc.store(self.ram_bank, value);
+                    self.ram_dirty = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn dump_ram(&self) -> Vec<u8> {
+        let mut buf = self.ram.clone();
+
+        if let Some(rtc) = self.rtc {
+            rtc.advanced(now()).write_footer(&self.latched, &mut buf);
+        }
+
+        buf
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        let size = self.ram.len();
+        let len = ::std::cmp::min(data.len(), size);
+        self.ram[..len].copy_from_slice(&data[..len]);
+
+        if self.rtc.is_some() && data.len() >= size + RTC_FOOTER_SIZE {
+            let (current, latched) = Rtc::read_footer(&data[size..]);
+            // Catch up with the time that passed while the emulator was closed.
+            self.rtc = Some(current.advanced(now()));
+            self.latched = latched;
+        }
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        ::std::mem::replace(&mut self.ram_dirty, false)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtc(days: u16, hours: u8, minutes: u8, seconds: u8) -> Rtc {
+        Rtc {
+            seconds,
+            minutes,
+            hours,
+            days,
+            halt: false,
+            carry: false,
+
+            timestamp: 1000,
+        }
+    }
+
+    #[test]
+    fn rolls_over_into_the_next_day() {
+        let rtc = rtc(0, 23, 59, 58).advanced(1003);
+
+        assert_eq!((rtc.days, rtc.hours, rtc.minutes, rtc.seconds), (1, 0, 0, 1));
+        assert!(!rtc.carry);
+        assert_eq!(rtc.timestamp, 1003);
+    }
+
+    #[test]
+    fn long_absence() {
+        // 2 days, 3 hours, 4 minutes and 5 seconds.
+        let elapsed = ((2 * 24 + 3) * 60 + 4) * 60 + 5;
+        let rtc = rtc(10, 0, 0, 0).advanced(1000 + elapsed);
+
+        assert_eq!((rtc.days, rtc.hours, rtc.minutes, rtc.seconds), (12, 3, 4, 5));
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let rtc = rtc(511, 23, 59, 59).advanced(1001);
+
+        assert_eq!((rtc.days, rtc.hours, rtc.minutes, rtc.seconds), (0, 0, 0, 0));
+        assert!(rtc.carry);
+        assert_eq!(rtc.load(0x0C) & 0x81, 0x80);
+
+        // Carry stays set until the game clears it.
+        let rtc = rtc.advanced(1002);
+        assert!(rtc.carry);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut halted = rtc(3, 4, 5, 6);
+        halted.halt = true;
+
+        let rtc = halted.advanced(5000);
+        assert_eq!((rtc.days, rtc.hours, rtc.minutes, rtc.seconds), (3, 4, 5, 6));
+        assert_eq!(rtc.timestamp, 5000);
+    }
+
+    #[test]
+    fn clock_going_backwards_does_not_advance() {
+        let rtc = rtc(3, 4, 5, 6).advanced(10);
+
+        assert_eq!((rtc.days, rtc.hours, rtc.minutes, rtc.seconds), (3, 4, 5, 6));
+    }
+}
@@ -0,0 +1,121 @@
+use mbc::{CartridgeHeader, MBC};
+
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_on: bool,
+    rom_bank: usize, // 9 bits, bank 0 is selectable.
+    ram_bank: usize,
+    rumble: bool,
+    motor: bool,
+    motor_changed: bool,
+    battery: bool,
+    ram_dirty: bool,
+}
+
+impl MBC5 {
+    pub fn new(data: Vec<u8>, header: &CartridgeHeader) -> ::error::Result<MBC5> {
+        let size = header.ram_bytes();
+
+        let rumble = header.cartridge_type.has_rumble();
+
+        let battery = header.cartridge_type.has_battery();
+
+        let ram: Vec<u8> = ::std::iter::repeat(0).take(size).collect();
+
+        Ok(MBC5 {
+            rom: data,
+            ram,
+            ram_on: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble,
+            motor: false,
+            motor_changed: false,
+            battery,
+            ram_dirty: false,
+        })
+    }
+
+    fn ram_address(&self, address: u16) -> usize {
+        ((self.ram_bank * 0x2000) | ((address & 0x1FFF) as usize)) % self.ram.len()
+    }
+}
+
+impl MBC for MBC5 {
+    fn readrom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank };
+
+        let addr = bank * 0x4000 | ((address as usize) & 0x3FFF);
+        *self.rom.get(addr % ::std::cmp::max(self.rom.len(), 1)).unwrap_or(&0xFF)
+    }
+
+    fn writerom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ... 0x1FFF => {
+                self.ram_on = value == 0x0A;
+            }
+            0x2000 ... 0x2FFF => {
+                self.rom_bank = (self.rom_bank & 0x100) | value as usize;
+            }
+            0x3000 ... 0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value & 0b1) as usize) << 8;
+            }
+            0x4000 ... 0x5FFF => {
+                if self.rumble {
+                    // Rumble boards wire RAM bank bit 3 to the motor.
+                    let motor = (value >> 3) & 0b1 == 1;
+                    if motor != self.motor {
+                        self.motor = motor;
+                        self.motor_changed = true;
+                    }
+                    self.ram_bank = (value & 0x07) as usize;
+                } else {
+                    self.ram_bank = (value & 0x0F) as usize;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn readram(&self, address: u16) -> u8 {
+        if !self.ram_on || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.ram_address(address)]
+    }
+
+    fn writeram(&mut self, address: u16, value: u8) {
+        if !self.ram_on || self.ram.is_empty() {
+            return;
+        }
+        let addr = self.ram_address(address);
+        self.ram[addr] = value;
+        self.ram_dirty = true;
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn dump_ram(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        let len = ::std::cmp::min(data.len(), self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        ::std::mem::replace(&mut self.ram_dirty, false)
+    }
+
+    fn rumble_event(&mut self) -> Option<bool> {
+        if self.motor_changed {
+            self.motor_changed = false;
+            return Some(self.motor);
+        }
+        None
+    }
+}
@@ -1,9 +1,22 @@
+use std::io;
 use std::io::prelude::*;
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use error::Error;
+
+mod header;
+mod patch;
 mod mbc0;
 mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+
+pub use self::header::{CartridgeHeader, CartridgeType};
 
 // Cartrige types
 
@@ -14,25 +27,25 @@ mod mbc1;
 // 00h ROM ONLY - Done.
 // 01h ROM+MBC1 - Done.
 // 02h ROM+MBC1+RAM - Done.
-// 03h ROM+MBC1+RAM+BATT
-// 05h ROM+MBC2
-// 06h ROM+MBC2+BATTERY
+// 03h ROM+MBC1+RAM+BATT - Done.
+// 05h ROM+MBC2 - Done.
+// 06h ROM+MBC2+BATTERY - Done.
 // 08h ROM+RAM                                               
 // 09h ROM+RAM+BATTERY
 // 0Bh ROM+MMM01
 // 0Ch ROM+MMM01+SRAM
 // 0Dh ROM+MMM01+SRAM+BATT     
-// 0Fh ROM+MBC3+TIMER+BATT     
-// 10h ROM+MBC3+TIMER+RAM+BATT
-// 11h ROM+MBC3
-// 12h ROM+MBC3+RAM
-// 13h ROM+MBC3+RAM+BATT
-// 19h ROM+MBC5
-// 1Ah ROM+MBC5+RAM
-// 1Bh ROM+MBC5+RAM+BATT
-// 1Ch ROM+MBC5+RUMBLE
-// 1Dh ROM+MBC5+RUMBLE+SRAM
-// 1Eh ROM+MBC5+RUMBLE+SRAM+BT
+// 0Fh ROM+MBC3+TIMER+BATT - Done.
+// 10h ROM+MBC3+TIMER+RAM+BATT - Done.
+// 11h ROM+MBC3 - Done.
+// 12h ROM+MBC3+RAM - Done.
+// 13h ROM+MBC3+RAM+BATT - Done.
+// 19h ROM+MBC5 - Done.
+// 1Ah ROM+MBC5+RAM - Done.
+// 1Bh ROM+MBC5+RAM+BATT - Done.
+// 1Ch ROM+MBC5+RUMBLE - Done.
+// 1Dh ROM+MBC5+RUMBLE+SRAM - Done.
+// 1Eh ROM+MBC5+RUMBLE+SRAM+BT - Done.
 // 1Fh Pocket Camera
 // FDh Bandai TAMA5
 // FEh Hudson HuC-3
@@ -46,62 +59,255 @@ pub trait MBC {
     fn readram(&self, address: u16) -> u8;
     
     fn writeram(&mut self, address: u16, value: u8);
+
+    fn has_battery(&self) -> bool {
+        false
+    }
+
+    // Contents of battery backed RAM in .sav layout, including any RTC footer.
+    fn dump_ram(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_ram(&mut self, _data: &[u8]) {}
+
+    // Returns whether RAM changed since the last call.
+    fn take_ram_dirty(&mut self) -> bool {
+        false
+    }
+
+    // Rumble motor state, returned once each time the cartridge switches it.
+    fn rumble_event(&mut self) -> Option<bool> {
+        None
+    }
 }
 
-pub fn get_mbc<P: AsRef<Path>>(path: P) -> ::StrResult<Box<MBC+'static>> {
-    let mut file = File::open(&path).unwrap();
+pub struct LoadOptions {
+    // Warn about a bad header checksum instead of refusing the ROM.
+    pub lenient_checksum: bool,
+
+    // IPS, UPS or BPS patch to apply. When unset a patch next to the ROM is picked up.
+    pub patch: Option<PathBuf>,
+
+    // Load <rom>.sav into battery backed RAM.
+    pub load_save: bool,
+}
+
+impl LoadOptions {
+    pub fn new() -> LoadOptions {
+        LoadOptions {
+            lenient_checksum: false,
+            patch: None,
+            load_save: true,
+        }
+    }
+}
+
+pub fn get_mbc<P: AsRef<Path>>(path: P, options: &LoadOptions) -> ::error::Result<(CartridgeHeader, Box<MBC+'static>)> {
+    let mut buf = read_rom(&path)?;
+
+    let patch_path = options.patch.clone().or_else(|| find_patch(&path));
+
+    if let Some(patch_path) = patch_path {
+        buf = apply_patch(&patch_path, buf)
+            .map_err(|e| Error::Patch { path: patch_path.clone(), source: Box::new(e) })?;
+        println!("Applied patch {}", patch_path.display());
+    }
+
+    let header = CartridgeHeader::parse(&buf)?;
+
+    if let Err(e) = check_checksum(&buf, &header) {
+        if !options.lenient_checksum {
+            return Err(e);
+        }
+        println!("Warning: {}", e);
+    }
+
+    let global = header::global_checksum(&buf);
+    if global != header.global_checksum {
+        println!("Global checksum mismatch: header says {:#06X}, computed {:#06X}", header.global_checksum, global);
+    }
+
+    if buf.len() < header.rom_bytes() {
+        return Err(Error::SizeMismatch { expected: header.rom_bytes(), actual: buf.len() });
+    }
+
+    let mut mbc = match header.cartridge_type {
+        CartridgeType::RomOnly => {
+            mbc0::MBC0::new(buf).map(|v| Box::new(v) as Box<MBC>)
+        }
+        CartridgeType::Mbc1 | CartridgeType::Mbc1Ram | CartridgeType::Mbc1RamBattery => {
+            mbc1::MBC1::new(buf, &header).map(|v| Box::new(v) as Box<MBC>)
+        }
+        CartridgeType::Mbc2 | CartridgeType::Mbc2Battery => {
+            mbc2::MBC2::new(buf, &header).map(|v| Box::new(v) as Box<MBC>)
+        }
+        CartridgeType::Mbc3TimerBattery
+        | CartridgeType::Mbc3TimerRamBattery
+        | CartridgeType::Mbc3
+        | CartridgeType::Mbc3Ram
+        | CartridgeType::Mbc3RamBattery => {
+            mbc3::MBC3::new(buf, &header).map(|v| Box::new(v) as Box<MBC>)
+        }
+        CartridgeType::Mbc5
+        | CartridgeType::Mbc5Ram
+        | CartridgeType::Mbc5RamBattery
+        | CartridgeType::Mbc5Rumble
+        | CartridgeType::Mbc5RumbleRam
+        | CartridgeType::Mbc5RumbleRamBattery => {
+            mbc5::MBC5::new(buf, &header).map(|v| Box::new(v) as Box<MBC>)
+        }
+        other => { Err(Error::UnsupportedMapper(other.code())) },
+    }?;
+
+    if options.load_save {
+        let save_path = save_path(&path);
+        load_save(&mut *mbc, &save_path).map_err(|e| Error::Save { path: save_path, source: e })?;
+    }
+
+    Ok((header, mbc))
+}
+
+// Loads battery backed RAM from a save file. A missing file is not an error, the cartridge
+// starts with blank RAM.
+fn load_save<P: AsRef<Path>>(mbc: &mut MBC, path: P) -> io::Result<()> {
+    if !mbc.has_battery() {
+        return Ok(());
+    }
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    mbc.load_ram(&data);
+
+    Ok(())
+}
+
+// Reads a raw ROM, the first .gb/.gbc entry of a .zip archive or a gzip compressed ROM.
+fn read_rom<P: AsRef<Path>>(path: P) -> ::error::Result<Vec<u8>> {
+    let file = File::open(&path)?;
 
     let mut buf = Vec::new();
 
-    file.read_to_end(&mut buf).unwrap();
-    
-    try!(check_checksum(&buf));
-    
-    match buf[0x147] {
-        0x00 => mbc0::MBC0::new(buf).map(|v| Box::new(v) as Box<MBC>),
-        0x01 ... 0x03 => mbc1::MBC1::new(buf).map(|v| Box::new(v) as Box<MBC>),
-        _ => { Err("Unsupported MBC type") },
+    match extension(path.as_ref()).as_ref().map(|e| e.as_str()) {
+        Some("zip") => {
+            let mut archive = ZipArchive::new(file)?;
+
+            let index = (0..archive.len())
+                .find(|&i| {
+                    archive.by_index(i).ok().map_or(false, |entry| {
+                        let name = entry.name().to_lowercase();
+                        name.ends_with(".gb") || name.ends_with(".gbc")
+                    })
+                })
+                .ok_or(Error::NoRomInArchive)?;
+
+            archive.by_index(index)?.read_to_end(&mut buf)?;
+        }
+        Some("gz") => {
+            GzDecoder::new(file).read_to_end(&mut buf)?;
+        }
+        _ => {
+            let mut file = file;
+            file.read_to_end(&mut buf)?;
+        }
+    }
+
+    Ok(buf)
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+// Path that patches and saves are named after: "game.gb.gz" and "game.zip" both become "game.*".
+fn base_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+
+    match extension(path).as_ref().map(|e| e.as_str()) {
+        Some("gz") => path.with_extension(""),
+        _ => path.to_path_buf(),
     }
 }
 
+fn apply_patch(path: &Path, rom: Vec<u8>) -> ::error::Result<Vec<u8>> {
+    let mut patch = Vec::new();
+    File::open(path)?.read_to_end(&mut patch)?;
+
+    patch::apply(&patch, rom)
+}
+
+// Looks for <rom>.ips, <rom>.ups or <rom>.bps.
+fn find_patch<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let path = base_path(path);
+
+    patch::EXTENSIONS
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|patch| patch.is_file())
+}
+
+pub fn save_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    base_path(path).with_extension("sav")
+}
+
+// Writes to a temporary file and renames it over the old save so a crash never leaves a torn file.
+pub fn write_save<P: AsRef<Path>>(mbc: &MBC, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("sav.tmp");
+
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&mbc.dump_ram())?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&tmp, path)
+}
+
 pub fn ram_size(v: u8) -> usize {
     match v {
         1 => 2 * 1024, // 1 bank
         2 => 8 * 1024, // 1 bank
         3 => 32 * 1024, // 4 banks
         4 => 128 * 1024, // 16 banks
+        5 => 64 * 1024, // 8 banks
         _ => 0,
     }
 }
 
-pub fn rom_size(v: u8) -> usize {
+pub fn rom_size(v: u8) -> Option<usize> {
     match v {
-        0 => 32 * 1024, // 2 bank
-        1 => 64 * 1024, // 4 bank
-        2 => 128 * 1024, // 8 banks
-        3 => 256 * 1024, // 16 banks
-        4 => 512 * 1024, // 32 banks
-        5 => 1 * 1024 * 1024, // 64 banks
-        6 => 2 * 1024 * 1024, // 128 banks
-        // 52 => 1.1 * 1024 * 1024, // 72 banks
-        // 53 => 1.2 * 1024 * 1024, // 80 banks
-        // 54 => 1.5 * 1024 * 1024, // 96 banks
-        _ => panic!("Unexpected rom size"),
-    }
-}
-
-fn check_checksum(data: &[u8]) -> ::StrResult<()> {
-    let mut value: u8 = 0;
-    for i in 0x134 .. 0x14D {
-        value = value.wrapping_sub(data[i]).wrapping_sub(1);
-    }
-    match data[0x14D] == value
+        0 => Some(32 * 1024), // 2 bank
+        1 => Some(64 * 1024), // 4 bank
+        2 => Some(128 * 1024), // 8 banks
+        3 => Some(256 * 1024), // 16 banks
+        4 => Some(512 * 1024), // 32 banks
+        5 => Some(1 * 1024 * 1024), // 64 banks
+        6 => Some(2 * 1024 * 1024), // 128 banks
+        7 => Some(4 * 1024 * 1024), // 256 banks
+        8 => Some(8 * 1024 * 1024), // 512 banks
+        0x52 => Some(72 * 16 * 1024), // 72 banks
+        0x53 => Some(80 * 16 * 1024), // 80 banks
+        0x54 => Some(96 * 16 * 1024), // 96 banks
+        _ => None,
+    }
+}
+
+fn check_checksum(data: &[u8], header: &CartridgeHeader) -> ::error::Result<()> {
+    let actual = header::header_checksum(data);
+    match actual == header.header_checksum
     {
         true => Ok(()),
-        false => Err("Cartridge checksum is invalid"),
+        false => Err(Error::BadHeaderChecksum { expected: header.header_checksum, actual }),
     }
 }
 
 pub fn bank_count(value: u8) -> u32 {
-    rom_size(value) as u32 / (1024 * 16)
+    rom_size(value).map_or(0, |size| size as u32 / (1024 * 16))
 }
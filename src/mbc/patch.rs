@@ -0,0 +1,531 @@
+use error::Error;
+
+// Soft-patching of ROM images with IPS, UPS and BPS patches.
+
+// IPS - "PATCH", records of 24-bit offset + 16-bit size (size 0 is a run-length record), "EOF".
+// UPS - "UPS1", XOR hunks between two variable length sizes, CRC32 footer.
+// BPS - "BPS1", copy/read actions with metadata, CRC32 footer.
+
+pub const EXTENSIONS: [&'static str; 3] = ["ips", "ups", "bps"];
+
+// source, target and patch CRC32, each 4 bytes.
+const FOOTER_SIZE: usize = 12;
+
+// Largest target a patch may declare, well above the biggest Game Boy ROM.
+const MAX_TARGET_SIZE: usize = 16 * 1024 * 1024;
+
+pub fn apply(patch: &[u8], rom: Vec<u8>) -> ::error::Result<Vec<u8>> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(patch, rom)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(patch, &rom)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(patch, &rom)
+    } else {
+        Err(Error::BadPatch("unknown patch format"))
+    }
+}
+
+fn apply_ips(patch: &[u8], mut rom: Vec<u8>) -> ::error::Result<Vec<u8>> {
+    let mut reader = Reader::new(patch, 5);
+
+    loop {
+        let offset = reader.be(3)?;
+
+        // 0x454F46 is "EOF".
+        if offset == 0x454F46 {
+            break;
+        }
+
+        let size = reader.be(2)?;
+
+        let (size, run) = if size == 0 {
+            let size = reader.be(2)?;
+            (size, Some(reader.byte()?))
+        } else {
+            (size, None)
+        };
+
+        if rom.len() < offset + size {
+            rom.resize(offset + size, 0);
+        }
+
+        match run {
+            Some(value) => {
+                for i in 0..size {
+                    rom[offset + i] = value;
+                }
+            }
+            None => {
+                let data = reader.bytes(size)?;
+                rom[offset..offset + size].copy_from_slice(data);
+            }
+        }
+    }
+
+    // Optional truncation extension.
+    if let Ok(size) = reader.be(3) {
+        rom.truncate(size);
+    }
+
+    Ok(rom)
+}
+
+fn apply_ups(patch: &[u8], source: &[u8]) -> ::error::Result<Vec<u8>> {
+    verify_footer("UPS", patch, source)?;
+
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], 4);
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+
+    if source.len() != source_size {
+        return Err(Error::BadPatch("UPS patch was made for a ROM of a different size"));
+    }
+
+    if target_size > MAX_TARGET_SIZE {
+        return Err(Error::BadPatch("UPS target size is too large"));
+    }
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+
+    let mut position: usize = 0;
+
+    while !reader.is_empty() {
+        position = position.saturating_add(reader.varint()?);
+
+        loop {
+            let value = reader.byte()?;
+
+            if position < target.len() {
+                target[position] ^= value;
+            }
+            position = position.checked_add(1).ok_or(Error::BadPatch("UPS hunk past the end of memory"))?;
+
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    verify_target("UPS", patch, &target)?;
+
+    Ok(target)
+}
+
+fn apply_bps(patch: &[u8], source: &[u8]) -> ::error::Result<Vec<u8>> {
+    verify_footer("BPS", patch, source)?;
+
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], 4);
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+
+    if source.len() != source_size {
+        return Err(Error::BadPatch("BPS patch was made for a ROM of a different size"));
+    }
+
+    if target_size > MAX_TARGET_SIZE {
+        return Err(Error::BadPatch("BPS target size is too large"));
+    }
+
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+
+    let mut source_offset: i64 = 0;
+    let mut target_offset: i64 = 0;
+
+    while !reader.is_empty() {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+
+        // Checked up front, a TargetCopy reading its own output would otherwise never run dry.
+        match target.len().checked_add(length) {
+            Some(end) if end <= target_size => {}
+            _ => return Err(Error::BadPatch("BPS patch writes past the target size")),
+        }
+
+        match data & 0b11 {
+            // SourceRead
+            0 => {
+                let start = target.len();
+                let data = source.get(start..start + length).ok_or(Error::BadPatch("BPS source read out of range"))?;
+                target.extend_from_slice(data);
+            }
+            // TargetRead
+            1 => {
+                let data = reader.bytes(length)?;
+                target.extend_from_slice(data);
+            }
+            // SourceCopy
+            2 => {
+                source_offset = source_offset
+                    .checked_add(relative(reader.varint()?))
+                    .ok_or(Error::BadPatch("BPS source copy out of range"))?;
+
+                for _ in 0..length {
+                    let value = *source.get(source_offset as usize).ok_or(Error::BadPatch("BPS source copy out of range"))?;
+                    target.push(value);
+                    source_offset += 1;
+                }
+            }
+            // TargetCopy, may overlap with the bytes it produces.
+            _ => {
+                target_offset = target_offset
+                    .checked_add(relative(reader.varint()?))
+                    .ok_or(Error::BadPatch("BPS target copy out of range"))?;
+
+                for _ in 0..length {
+                    let value = *target.get(target_offset as usize).ok_or(Error::BadPatch("BPS target copy out of range"))?;
+                    target.push(value);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(Error::BadPatch("BPS patch does not produce the declared target size"));
+    }
+
+    verify_target("BPS", patch, &target)?;
+
+    Ok(target)
+}
+
+fn relative(data: usize) -> i64 {
+    let value = (data >> 1) as i64;
+    if data & 1 == 1 { -value } else { value }
+}
+
+fn footer(patch: &[u8], index: usize) -> u32 {
+    let at = patch.len() - FOOTER_SIZE + index * 4;
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&patch[at..at + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+// Checks the patch itself and the ROM it is applied to.
+fn verify_footer(kind: &'static str, patch: &[u8], source: &[u8]) -> ::error::Result<()> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err(Error::BadPatch("patch is truncated"));
+    }
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != footer(patch, 2) {
+        return Err(Error::PatchChecksum { kind, what: "patch", expected: footer(patch, 2), actual });
+    }
+
+    let actual = crc32(source);
+    if actual != footer(patch, 0) {
+        return Err(Error::PatchChecksum { kind, what: "source ROM", expected: footer(patch, 0), actual });
+    }
+
+    Ok(())
+}
+
+fn verify_target(kind: &'static str, patch: &[u8], target: &[u8]) -> ::error::Result<()> {
+    let actual = crc32(target);
+    if actual != footer(patch, 1) {
+        return Err(Error::PatchChecksum { kind, what: "patched ROM", expected: footer(patch, 1), actual });
+    }
+    Ok(())
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Reader<'a> {
+        Reader { data, position }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn byte(&mut self) -> ::error::Result<u8> {
+        let value = *self.data.get(self.position).ok_or(Error::BadPatch("patch is truncated"))?;
+        self.position += 1;
+        Ok(value)
+    }
+
+    fn bytes(&mut self, size: usize) -> ::error::Result<&'a [u8]> {
+        let end = self.position.checked_add(size).ok_or(Error::BadPatch("patch is truncated"))?;
+        let data = self.data
+            .get(self.position..end)
+            .ok_or(Error::BadPatch("patch is truncated"))?;
+        self.position = end;
+        Ok(data)
+    }
+
+    // Big-endian number, used by IPS.
+    fn be(&mut self, size: usize) -> ::error::Result<usize> {
+        let mut value = 0;
+        for _ in 0..size {
+            value = value << 8 | self.byte()? as usize;
+        }
+        Ok(value)
+    }
+
+    // Variable length number shared by UPS and BPS, every byte carries an implicit +1.
+    fn varint(&mut self) -> ::error::Result<usize> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.byte()?;
+            let digit = ((byte & 0x7F) as usize).checked_mul(shift).ok_or(Error::BadPatch("number overflow"))?;
+            value = value.checked_add(digit).ok_or(Error::BadPatch("number overflow"))?;
+
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+
+            shift = shift.checked_mul(128).ok_or(Error::BadPatch("number overflow"))?;
+            value = value.checked_add(shift).ok_or(Error::BadPatch("number overflow"))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let x = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(0x80 | x);
+                return out;
+            }
+            out.push(x);
+            value -= 1;
+        }
+    }
+
+    // Appends the source, target and patch CRC32 footer.
+    fn finish(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    fn bad_patch(result: ::error::Result<Vec<u8>>) -> bool {
+        match result {
+            Err(Error::BadPatch(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn checksum_mismatch(result: ::error::Result<Vec<u8>>) -> Option<&'static str> {
+        match result {
+            Err(Error::PatchChecksum { what, .. }) => Some(what),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn ips_record() {
+        let patch = b"PATCH\x00\x00\x02\x00\x03XYZ\x00\x00\x08\x00\x01!EOF";
+        let rom = apply(patch, b"abcdef".to_vec()).unwrap();
+        assert_eq!(rom, b"abXYZf\x00\x00!".to_vec());
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let patch = b"PATCH\x00\x00\x01\x00\x00\x00\x03-EOF";
+        let rom = apply(patch, b"abcdef".to_vec()).unwrap();
+        assert_eq!(rom, b"a---ef".to_vec());
+    }
+
+    #[test]
+    fn ips_truncation() {
+        let patch = b"PATCH\x00\x00\x00\x00\x01ZEOF\x00\x00\x03";
+        let rom = apply(patch, b"abcdef".to_vec()).unwrap();
+        assert_eq!(rom, b"Zbc".to_vec());
+    }
+
+    #[test]
+    fn ips_truncated_record() {
+        assert!(bad_patch(apply(b"PATCH\x00\x00\x00\x00\x05ab", b"abcdef".to_vec())));
+    }
+
+    fn ups_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+
+        // Each run of differing bytes becomes a hunk terminated by a zero XOR byte.
+        let size = ::std::cmp::max(source.len(), target.len());
+        let xor = |i: usize| source.get(i).unwrap_or(&0) ^ target.get(i).unwrap_or(&0);
+
+        let mut last = 0;
+        let mut i = 0;
+        while i < size {
+            if xor(i) == 0 {
+                i += 1;
+                continue;
+            }
+
+            patch.extend(varint(i - last));
+            while i < size && xor(i) != 0 {
+                patch.push(xor(i));
+                i += 1;
+            }
+            patch.push(0);
+
+            i += 1;
+            last = i;
+        }
+
+        finish(patch, source, target)
+    }
+
+    #[test]
+    fn ups_round_trip() {
+        let source = b"Hello, Game Boy!".to_vec();
+        let target = b"Hallo, Game Boy!!!".to_vec();
+
+        let patch = ups_patch(&source, &target);
+        assert_eq!(apply(&patch, source).unwrap(), target);
+    }
+
+    #[test]
+    fn ups_patch_checksum_mismatch() {
+        let source = b"abcdef".to_vec();
+        let mut patch = ups_patch(&source, b"abXdef");
+        let len = patch.len();
+        patch[len - 1] ^= 0xFF;
+
+        assert_eq!(checksum_mismatch(apply(&patch, source)), Some("patch"));
+    }
+
+    #[test]
+    fn ups_source_checksum_mismatch() {
+        let patch = ups_patch(b"abcdef", b"abXdef");
+        assert_eq!(checksum_mismatch(apply(&patch, b"zzzzzz".to_vec())), Some("source ROM"));
+    }
+
+    // BPS patch from a list of already encoded actions.
+    fn bps_patch(source: &[u8], target_size: usize, target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target_size));
+        patch.extend(varint(3));
+        patch.extend_from_slice(b"xyz");
+        patch.extend_from_slice(actions);
+
+        finish(patch, source, target)
+    }
+
+    fn action(command: usize, length: usize) -> Vec<u8> {
+        varint((length - 1) << 2 | command)
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let source = b"0123456789".to_vec();
+        let target = b"012ABABABA789".to_vec();
+
+        let mut actions = Vec::new();
+        // SourceRead "012"
+        actions.extend(action(0, 3));
+        // TargetRead "AB"
+        actions.extend(action(1, 2));
+        actions.extend_from_slice(b"AB");
+        // TargetCopy from offset 3, overlapping the bytes it writes: "ABABA"
+        actions.extend(action(3, 5));
+        actions.extend(varint(3 << 1));
+        // SourceCopy "789" from offset 7
+        actions.extend(action(2, 3));
+        actions.extend(varint(7 << 1));
+
+        let patch = bps_patch(&source, target.len(), &target, &actions);
+        assert_eq!(apply(&patch, source).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_target_checksum_mismatch() {
+        let source = b"0123".to_vec();
+        let actions = action(0, 4);
+        let patch = bps_patch(&source, 4, b"9999", &actions);
+
+        assert_eq!(checksum_mismatch(apply(&patch, source)), Some("patched ROM"));
+    }
+
+    #[test]
+    fn bps_source_checksum_mismatch() {
+        let actions = action(0, 4);
+        let patch = bps_patch(b"0123", 4, b"0123", &actions);
+
+        assert_eq!(checksum_mismatch(apply(&patch, b"4567".to_vec())), Some("source ROM"));
+    }
+
+    #[test]
+    fn bps_patch_checksum_mismatch() {
+        let source = b"0123".to_vec();
+        let mut patch = bps_patch(&source, 4, b"0123", &action(0, 4));
+        patch[4] ^= 0x01;
+
+        assert_eq!(checksum_mismatch(apply(&patch, source)), Some("patch"));
+    }
+
+    #[test]
+    fn bps_rejects_oversized_length() {
+        let source = b"0123".to_vec();
+
+        let mut actions = Vec::new();
+        actions.extend(action(1, 1));
+        actions.push(b'A');
+        // TargetCopy of a huge run that would keep reading its own output.
+        actions.extend(action(3, 1 << 40));
+        actions.extend(varint(0));
+
+        let patch = bps_patch(&source, 16, b"", &actions);
+        assert!(bad_patch(apply(&patch, source)));
+    }
+
+    #[test]
+    fn bps_rejects_oversized_metadata() {
+        let source = b"0123".to_vec();
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(4));
+        patch.extend(varint(usize::max_value() - 8));
+        let patch = finish(patch, &source, b"0123");
+
+        assert!(bad_patch(apply(&patch, source)));
+    }
+}
@@ -8,6 +8,9 @@ pub struct Serial {
     pub transfer_flag: bool, // 0 - non-transfer; 1 - transfer;
 
     pub clock: bool, // 0 - external clock; 1 - internal clock;
+
+    // Bytes shifted out with the internal clock, e.g. test ROM results.
+    pub output: Vec<u8>,
 }
 
 impl Serial {
@@ -19,6 +22,8 @@ impl Serial {
             transfer_flag: false,
 
             clock: false,
+
+            output: Vec::new(),
         }
     }
 }
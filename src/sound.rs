@@ -1,61 +1,713 @@
-pub struct Sound {
-    pub sweep_time: f32,
-    pub sweep_mode: bool, // 0 - Increase; 1 - Decrease;
-    pub shift: u32,
+// Samples are produced once per M-cycle, the frontend resamples them to the host rate.
+pub const SAMPLE_RATE: u32 = 1024 * 1024;
+
+// Keep at most one second of audio if nobody drains the buffer.
+const MAX_SAMPLES: usize = SAMPLE_RATE as usize * 2;
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
 
-    pub wave_pattern: f32,
-    pub length: u32,
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
 
-    pub initial_volume: u16,
-    pub direction: bool, // 0 - Increase; 1 - Decrease;
-    pub sweeps: u8,
+pub struct Envelope {
+    pub initial_volume: u8,
+    pub direction: bool, // 0 - Decrease; 1 - Increase;
+    pub period: u8,
+
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            initial_volume: 0,
+            direction: false,
+            period: 0,
+
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn load(&self) -> u8 {
+        self.initial_volume << 4 | (self.direction as u8) << 3 | self.period
+    }
 
-    pub initial: u8,
-    pub counter: u8,
-    pub frequency: u8,
+    fn store(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.direction = (value >> 3) & 0b1 == 1;
+        self.period = value & 0b111;
+    }
+
+    // The DAC is powered whenever the upper five bits of NRx2 are not all zero.
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.direction
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period;
+
+            if self.direction && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.direction && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
 
+pub struct Square {
     pub enable: bool,
 
-    pub volume: u32,
+    // Sweep is only wired up on channel 1.
+    pub sweep_period: u8,
+    pub sweep_mode: bool, // 0 - Increase; 1 - Decrease;
+    pub shift: u8,
+
+    pub duty: u8,
+    pub length: u16,
+    pub length_enable: bool,
 
-    pub shift_clock: u32,
-    pub width: u32,
-    pub ratio: u32,
+    pub envelope: Envelope,
 
-    pub data: [u8; 32],
+    pub frequency: u16,
 
-    pub waveram: [u8; 32],
+    timer: u32,
+    position: usize,
+
+    sweep_enable: bool,
+    sweep_timer: u8,
+    shadow_frequency: u16,
 }
 
-impl Sound {
-    pub fn new() -> Sound {
-        Sound {
-            sweep_time: 0.0,
+impl Square {
+    fn new() -> Square {
+        Square {
+            enable: false,
+
+            sweep_period: 0,
             sweep_mode: false,
             shift: 0,
 
-            wave_pattern: 50.0,
+            duty: 0,
             length: 0,
+            length_enable: false,
 
-            initial_volume: 0,
-            direction: false,
-            sweeps: 0,
+            envelope: Envelope::new(),
 
-            initial: 0,
-            counter: 0,
             frequency: 0,
 
+            timer: 0,
+            position: 0,
+
+            sweep_enable: false,
+            sweep_timer: 0,
+            shadow_frequency: 0,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enable = self.envelope.dac_enabled();
+
+        if self.length == 0 {
+            self.length = 64;
+        }
+
+        self.timer = (2048 - self.frequency as u32) * 4;
+        self.envelope.trigger();
+
+        self.shadow_frequency = self.frequency;
+        self.sweep_timer = if self.sweep_period == 0 { 8 } else { self.sweep_period };
+        self.sweep_enable = self.sweep_period != 0 || self.shift != 0;
+
+        if self.shift != 0 {
+            self.calculate_sweep();
+        }
+    }
+
+    fn calculate_sweep(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift;
+
+        let value = if self.sweep_mode {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        };
+
+        if value > 2047 {
+            self.enable = false;
+        }
+
+        value
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+
+        if self.sweep_timer != 0 {
+            return;
+        }
+
+        self.sweep_timer = if self.sweep_period == 0 { 8 } else { self.sweep_period };
+
+        if !self.sweep_enable || self.sweep_period == 0 {
+            return;
+        }
+
+        let value = self.calculate_sweep();
+
+        if value <= 2047 && self.shift != 0 {
+            self.frequency = value;
+            self.shadow_frequency = value;
+
+            // Overflow is checked a second time with the new frequency.
+            self.calculate_sweep();
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length_enable && self.length > 0 {
+            self.length -= 1;
+
+            if self.length == 0 {
+                self.enable = false;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency as u32) * 4;
+            self.position = (self.position + 1) % 8;
+        }
+    }
+
+    fn output(&self) -> Option<u8> {
+        if !self.envelope.dac_enabled() {
+            return None;
+        }
+
+        if !self.enable {
+            return Some(0);
+        }
+
+        Some(DUTY_PATTERNS[self.duty as usize][self.position] * self.envelope.volume)
+    }
+}
+
+pub struct Wave {
+    pub enable: bool,
+    pub dac: bool,
+
+    pub length: u16,
+    pub length_enable: bool,
+
+    pub volume: u8, // 0 - Mute; 1 - 100%; 2 - 50%; 3 - 25%;
+
+    pub frequency: u16,
+
+    pub waveram: [u8; 16],
+
+    timer: u32,
+    position: usize,
+}
+
+impl Wave {
+    fn new() -> Wave {
+        Wave {
             enable: false,
+            dac: false,
+
+            length: 0,
+            length_enable: false,
 
             volume: 0,
 
+            frequency: 0,
+
+            waveram: [0; 16],
+
+            timer: 0,
+            position: 0,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enable = self.dac;
+
+        if self.length == 0 {
+            self.length = 256;
+        }
+
+        self.timer = (2048 - self.frequency as u32) * 2;
+        self.position = 0;
+    }
+
+    fn clock_length(&mut self) {
+        if self.length_enable && self.length > 0 {
+            self.length -= 1;
+
+            if self.length == 0 {
+                self.enable = false;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency as u32) * 2;
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    fn output(&self) -> Option<u8> {
+        if !self.dac {
+            return None;
+        }
+
+        if !self.enable {
+            return Some(0);
+        }
+
+        // Two 4-bit samples per byte, upper nibble first.
+        let byte = self.waveram[self.position / 2];
+        let sample = if self.position % 2 == 0 { byte >> 4 } else { byte & 0xF };
+
+        match self.volume {
+            0 => Some(0),
+            1 => Some(sample),
+            2 => Some(sample >> 1),
+            3 => Some(sample >> 2),
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub struct Noise {
+    pub enable: bool,
+
+    pub length: u16,
+    pub length_enable: bool,
+
+    pub envelope: Envelope,
+
+    pub shift_clock: u8,
+    pub width: bool, // 0 - 15 bit; 1 - 7 bit;
+    pub ratio: u8,
+
+    timer: u32,
+    lfsr: u16,
+}
+
+impl Noise {
+    fn new() -> Noise {
+        Noise {
+            enable: false,
+
+            length: 0,
+            length_enable: false,
+
+            envelope: Envelope::new(),
+
             shift_clock: 0,
-            width: 0,
+            width: false,
             ratio: 0,
 
-            data: [0; 32],
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+
+    fn period(&self) -> u32 {
+        NOISE_DIVISORS[self.ratio as usize] << self.shift_clock
+    }
+
+    fn trigger(&mut self) {
+        self.enable = self.envelope.dac_enabled();
+
+        if self.length == 0 {
+            self.length = 64;
+        }
+
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+
+    fn clock_length(&mut self) {
+        if self.length_enable && self.length > 0 {
+            self.length -= 1;
+
+            if self.length == 0 {
+                self.enable = false;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+
+            let bit = (self.lfsr & 0b1) ^ ((self.lfsr >> 1) & 0b1);
+
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+
+            if self.width {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+            }
+        }
+    }
+
+    fn output(&self) -> Option<u8> {
+        if !self.envelope.dac_enabled() {
+            return None;
+        }
+
+        if !self.enable {
+            return Some(0);
+        }
+
+        Some((!self.lfsr & 0b1) as u8 * self.envelope.volume)
+    }
+}
+
+pub struct Sound {
+    pub channel_1: Square,
+    pub channel_2: Square,
+    pub channel_3: Wave,
+    pub channel_4: Noise,
+
+    pub enable: bool,
+
+    pub volume_left: u8,
+    pub volume_right: u8,
+
+    // NR51, bits 4-7 route channels 1-4 to the left output, bits 0-3 to the right.
+    pub panning: u8,
+
+    pub output: bool,
+
+    // Interleaved left/right samples at SAMPLE_RATE.
+    pub samples: Vec<f32>,
+
+    sequencer_step: u8,
+    cycles: u32,
+}
+
+impl Sound {
+    pub fn new() -> Sound {
+        Sound {
+            channel_1: Square::new(),
+            channel_2: Square::new(),
+            channel_3: Wave::new(),
+            channel_4: Noise::new(),
+
+            enable: true,
+
+            volume_left: 0,
+            volume_right: 0,
+
+            panning: 0,
+
+            output: false,
+
+            samples: Vec::new(),
+
+            sequencer_step: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn drain_samples(&mut self, buffer: &mut Vec<f32>) {
+        buffer.extend(self.samples.drain(..));
+    }
+
+    pub fn step(&mut self, cycles: u16) {
+        for _ in 0..cycles {
+            if self.enable {
+                self.channel_1.step();
+                self.channel_2.step();
+                self.channel_3.step();
+                self.channel_4.step();
+            }
+
+            self.cycles += 1;
+
+            if self.cycles == 4 {
+                self.cycles = 0;
+
+                if self.output {
+                    self.mix();
+                }
+            }
+        }
+    }
+
+    // Clocked at 512 Hz by a falling edge of DIV bit 4.
+    pub fn step_frame_sequencer(&mut self) {
+        if !self.enable {
+            return;
+        }
+
+        if self.sequencer_step % 2 == 0 {
+            self.channel_1.clock_length();
+            self.channel_2.clock_length();
+            self.channel_3.clock_length();
+            self.channel_4.clock_length();
+        }
+
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            self.channel_1.clock_sweep();
+        }
+
+        if self.sequencer_step == 7 {
+            self.channel_1.envelope.clock();
+            self.channel_2.envelope.clock();
+            self.channel_4.envelope.clock();
+        }
+
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    fn mix(&mut self) {
+        let outputs = [
+            self.channel_1.output(),
+            self.channel_2.output(),
+            self.channel_3.output(),
+            self.channel_4.output(),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (i, output) in outputs.iter().enumerate() {
+            // A disabled DAC outputs silence instead of its 0 level.
+            let value = match *output {
+                Some(digital) => digital as f32 / 7.5 - 1.0,
+                None => 0.0,
+            };
+
+            if (self.panning >> (i + 4)) & 0b1 == 1 {
+                left += value;
+            }
+
+            if (self.panning >> i) & 0b1 == 1 {
+                right += value;
+            }
+        }
+
+        left = left / 4.0 * (self.volume_left as f32 + 1.0) / 8.0;
+        right = right / 4.0 * (self.volume_right as f32 + 1.0) / 8.0;
+
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.clear();
+        }
+
+        self.samples.push(left);
+        self.samples.push(right);
+    }
+
+    pub fn load(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10 => {
+                0x80 | self.channel_1.sweep_period << 4 | (self.channel_1.sweep_mode as u8) << 3
+                    | self.channel_1.shift
+            }
+            0xFF11 => 0x3F | self.channel_1.duty << 6,
+            0xFF12 => self.channel_1.envelope.load(),
+            0xFF14 => 0xBF | (self.channel_1.length_enable as u8) << 6,
+            0xFF16 => 0x3F | self.channel_2.duty << 6,
+            0xFF17 => self.channel_2.envelope.load(),
+            0xFF19 => 0xBF | (self.channel_2.length_enable as u8) << 6,
+            0xFF1A => 0x7F | (self.channel_3.dac as u8) << 7,
+            0xFF1C => 0x9F | self.channel_3.volume << 5,
+            0xFF1E => 0xBF | (self.channel_3.length_enable as u8) << 6,
+            0xFF21 => self.channel_4.envelope.load(),
+            0xFF22 => {
+                self.channel_4.shift_clock << 4 | (self.channel_4.width as u8) << 3
+                    | self.channel_4.ratio
+            }
+            0xFF23 => 0xBF | (self.channel_4.length_enable as u8) << 6,
+            0xFF24 => self.volume_left << 4 | self.volume_right,
+            0xFF25 => self.panning,
+            0xFF26 => {
+                (self.enable as u8) << 7 | 0x70 | (self.channel_4.enable as u8) << 3
+                    | (self.channel_3.enable as u8) << 2
+                    | (self.channel_2.enable as u8) << 1
+                    | self.channel_1.enable as u8
+            }
+            0xFF30 ... 0xFF3F => self.channel_3.waveram[(addr - 0xFF30) as usize],
+            // Frequency and length registers are write only.
+            _ => 0xFF,
+        }
+    }
+
+    pub fn store(&mut self, addr: u16, value: u8) {
+        if let 0xFF30 ... 0xFF3F = addr {
+            return self.channel_3.waveram[(addr - 0xFF30) as usize] = value;
+        }
+
+        if addr == 0xFF26 {
+            let enable = (value >> 7) & 0b1 == 1;
+
+            if self.enable && !enable {
+                // Powering off clears every register except wave RAM.
+                let waveram = self.channel_3.waveram;
+
+                *self = Sound {
+                    output: self.output,
+                    samples: ::std::mem::replace(&mut self.samples, Vec::new()),
+                    ..Sound::new()
+                };
+
+                self.channel_3.waveram = waveram;
+            }
+
+            if !self.enable && enable {
+                self.sequencer_step = 0;
+            }
+
+            return self.enable = enable;
+        }
+
+        // While powered off every register except NR52 ignores writes.
+        if !self.enable {
+            return;
+        }
 
-            waveram: [0; 32],
+        match addr {
+            0xFF10 => {
+                self.channel_1.sweep_period = (value >> 4) & 0b111;
+                self.channel_1.sweep_mode = (value >> 3) & 0b1 == 1;
+                self.channel_1.shift = value & 0b111;
+            }
+            0xFF11 => {
+                self.channel_1.duty = value >> 6;
+                self.channel_1.length = 64 - (value & 0x3F) as u16;
+            }
+            0xFF12 => {
+                self.channel_1.envelope.store(value);
+                if !self.channel_1.envelope.dac_enabled() {
+                    self.channel_1.enable = false;
+                }
+            }
+            0xFF13 => {
+                self.channel_1.frequency = (self.channel_1.frequency & 0x700) | value as u16;
+            }
+            0xFF14 => {
+                self.channel_1.frequency =
+                    (self.channel_1.frequency & 0xFF) | ((value & 0b111) as u16) << 8;
+                self.channel_1.length_enable = (value >> 6) & 0b1 == 1;
+                if (value >> 7) & 0b1 == 1 {
+                    self.channel_1.trigger();
+                }
+            }
+            0xFF16 => {
+                self.channel_2.duty = value >> 6;
+                self.channel_2.length = 64 - (value & 0x3F) as u16;
+            }
+            0xFF17 => {
+                self.channel_2.envelope.store(value);
+                if !self.channel_2.envelope.dac_enabled() {
+                    self.channel_2.enable = false;
+                }
+            }
+            0xFF18 => {
+                self.channel_2.frequency = (self.channel_2.frequency & 0x700) | value as u16;
+            }
+            0xFF19 => {
+                self.channel_2.frequency =
+                    (self.channel_2.frequency & 0xFF) | ((value & 0b111) as u16) << 8;
+                self.channel_2.length_enable = (value >> 6) & 0b1 == 1;
+                if (value >> 7) & 0b1 == 1 {
+                    self.channel_2.trigger();
+                }
+            }
+            0xFF1A => {
+                self.channel_3.dac = (value >> 7) & 0b1 == 1;
+                if !self.channel_3.dac {
+                    self.channel_3.enable = false;
+                }
+            }
+            0xFF1B => {
+                self.channel_3.length = 256 - value as u16;
+            }
+            0xFF1C => {
+                self.channel_3.volume = (value >> 5) & 0b11;
+            }
+            0xFF1D => {
+                self.channel_3.frequency = (self.channel_3.frequency & 0x700) | value as u16;
+            }
+            0xFF1E => {
+                self.channel_3.frequency =
+                    (self.channel_3.frequency & 0xFF) | ((value & 0b111) as u16) << 8;
+                self.channel_3.length_enable = (value >> 6) & 0b1 == 1;
+                if (value >> 7) & 0b1 == 1 {
+                    self.channel_3.trigger();
+                }
+            }
+            0xFF20 => {
+                self.channel_4.length = 64 - (value & 0x3F) as u16;
+            }
+            0xFF21 => {
+                self.channel_4.envelope.store(value);
+                if !self.channel_4.envelope.dac_enabled() {
+                    self.channel_4.enable = false;
+                }
+            }
+            0xFF22 => {
+                self.channel_4.shift_clock = value >> 4;
+                self.channel_4.width = (value >> 3) & 0b1 == 1;
+                self.channel_4.ratio = value & 0b111;
+            }
+            0xFF23 => {
+                self.channel_4.length_enable = (value >> 6) & 0b1 == 1;
+                if (value >> 7) & 0b1 == 1 {
+                    self.channel_4.trigger();
+                }
+            }
+            0xFF24 => {
+                // Bits 7 and 3 route the cartridge VIN line, which no cartridge we support drives.
+                self.volume_left = (value >> 4) & 0b111;
+                self.volume_right = value & 0b111;
+            }
+            0xFF25 => {
+                self.panning = value;
+            }
+            _ => {}
         }
     }
 }